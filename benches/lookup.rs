@@ -0,0 +1,178 @@
+//! Compares the cost of casts for cast lists of different lengths, for shared casts to the last
+//! listed trait (hit) and to a trait that is not listed (miss), and for mutable and boxed casts to
+//! the last listed trait.
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use downcast_trait::prelude::*;
+
+macro_rules! traits {
+    ($($name:ident),+) => {
+        $(
+        #[allow(dead_code)]
+        trait $name {
+            fn id(&self) -> usize;
+        }
+        impl<const N: usize> $name for Listed<N> {
+            fn id(&self) -> usize {
+                N
+            }
+        }
+        )+
+    };
+}
+
+struct Listed<const N: usize>;
+trait Missing {}
+
+traits!(
+    T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12, T13, T14, T15, T16, T17, T18, T19, T20,
+    T21, T22, T23, T24, T25, T26, T27, T28, T29, T30, T31, T32, T33, T34, T35, T36, T37, T38, T39,
+    T40, T41, T42, T43, T44, T45, T46, T47, T48, T49, T50, T51, T52, T53, T54, T55, T56, T57, T58,
+    T59, T60, T61, T62, T63
+);
+
+impl DowncastTrait for Listed<1> {
+    downcast_trait_impl_convert_to!(dyn T0);
+}
+
+impl DowncastTrait for Listed<4> {
+    downcast_trait_impl_convert_to!(dyn T0, dyn T1, dyn T2, dyn T3);
+}
+
+impl DowncastTrait for Listed<16> {
+    downcast_trait_impl_convert_to!(
+        dyn T0,
+        dyn T1,
+        dyn T2,
+        dyn T3,
+        dyn T4,
+        dyn T5,
+        dyn T6,
+        dyn T7,
+        dyn T8,
+        dyn T9,
+        dyn T10,
+        dyn T11,
+        dyn T12,
+        dyn T13,
+        dyn T14,
+        dyn T15
+    );
+}
+
+impl DowncastTrait for Listed<64> {
+    downcast_trait_impl_convert_to!(
+        dyn T0,
+        dyn T1,
+        dyn T2,
+        dyn T3,
+        dyn T4,
+        dyn T5,
+        dyn T6,
+        dyn T7,
+        dyn T8,
+        dyn T9,
+        dyn T10,
+        dyn T11,
+        dyn T12,
+        dyn T13,
+        dyn T14,
+        dyn T15,
+        dyn T16,
+        dyn T17,
+        dyn T18,
+        dyn T19,
+        dyn T20,
+        dyn T21,
+        dyn T22,
+        dyn T23,
+        dyn T24,
+        dyn T25,
+        dyn T26,
+        dyn T27,
+        dyn T28,
+        dyn T29,
+        dyn T30,
+        dyn T31,
+        dyn T32,
+        dyn T33,
+        dyn T34,
+        dyn T35,
+        dyn T36,
+        dyn T37,
+        dyn T38,
+        dyn T39,
+        dyn T40,
+        dyn T41,
+        dyn T42,
+        dyn T43,
+        dyn T44,
+        dyn T45,
+        dyn T46,
+        dyn T47,
+        dyn T48,
+        dyn T49,
+        dyn T50,
+        dyn T51,
+        dyn T52,
+        dyn T53,
+        dyn T54,
+        dyn T55,
+        dyn T56,
+        dyn T57,
+        dyn T58,
+        dyn T59,
+        dyn T60,
+        dyn T61,
+        dyn T62,
+        dyn T63
+    );
+}
+
+/// Casts to the last listed trait of a list, as a shared, mutable and boxed cast.
+struct Last {
+    by_ref: fn(&dyn DowncastTrait) -> Option<usize>,
+    by_mut: fn(&mut dyn DowncastTrait) -> Option<usize>,
+    by_box: fn(Box<dyn DowncastTrait>) -> Option<usize>,
+}
+
+macro_rules! last {
+    ($type:path) => {
+        Last {
+            by_ref: |o| downcast_trait!(dyn $type, o).map(|t| t.id()),
+            by_mut: |o| downcast_trait_mut!(dyn $type, o).map(|t| t.id()),
+            by_box: |o| downcast_trait_box!(dyn $type, o).ok().map(|t| t.id()),
+        }
+    };
+}
+
+fn bench_list<const N: usize>(c: &mut Criterion, last: Last)
+where
+    Listed<N>: DowncastTrait,
+{
+    let mut listed = Listed::<N>;
+    let object: &dyn DowncastTrait = &listed;
+    c.bench_function(&format!("ref hit, {} traits", N), |b| {
+        b.iter(|| (last.by_ref)(black_box(object)))
+    });
+    c.bench_function(&format!("ref miss, {} traits", N), |b| {
+        b.iter(|| downcast_trait!(dyn Missing, black_box(object)).is_some())
+    });
+    let object: &mut dyn DowncastTrait = &mut listed;
+    c.bench_function(&format!("mut hit, {} traits", N), |b| {
+        b.iter(|| (last.by_mut)(black_box(&mut *object)))
+    });
+    // Listed is zero sized, so boxing it does not allocate and the cast dominates.
+    c.bench_function(&format!("box hit, {} traits", N), |b| {
+        b.iter(|| (last.by_box)(black_box(Box::new(Listed::<N>))))
+    });
+}
+
+fn lookup(c: &mut Criterion) {
+    bench_list::<1>(c, last!(T0));
+    bench_list::<4>(c, last!(T3));
+    bench_list::<16>(c, last!(T15));
+    bench_list::<64>(c, last!(T63));
+}
+
+criterion_group!(benches, lookup);
+criterion_main!(benches);
@@ -0,0 +1,195 @@
+//!
+//! Derive macro for the [downcast-trait](https://crates.io/crates/downcast-trait) crate.
+//!
+//! This crate is normally used through the `derive` feature of downcast-trait, which re-exports
+//! the macro next to the trait with the same name. The derive generates the same implementation
+//! as [downcast_trait_impl_convert_to](../downcast_trait/macro.downcast_trait_impl_convert_to.html)
+//! from the traits listed in one or more `#[downcast(...)]` attributes:
+//!
+//! ```
+//! use downcast_trait::{downcast_trait, DowncastTrait};
+//! trait Container {
+//!     fn len(&self) -> usize;
+//! }
+//! #[derive(DowncastTrait)]
+//! #[downcast(dyn Container)]
+//! struct Window<T: Clone> {
+//!     children: Vec<T>,
+//! }
+//! impl<T: Clone> Container for Window<T> {
+//!     fn len(&self) -> usize {
+//!         self.children.len()
+//!     }
+//! }
+//! let window = Window { children: vec![1, 2, 3] };
+//! let container = downcast_trait!(dyn Container, window.to_downcast_trait()).unwrap();
+//! assert_eq!(container.len(), 3);
+//! ```
+//!
+//! The generated code refers to the crate as `::downcast_trait`. If it is renamed or re-exported
+//! under another path, the path can be given with `#[downcast(crate = path)]`:
+//! ```
+//! extern crate downcast_trait as widgets;
+//! trait Container {}
+//! #[derive(widgets::DowncastTrait)]
+//! #[downcast(crate = widgets)]
+//! #[downcast(dyn Container)]
+//! struct Window;
+//! impl Container for Window {}
+//! ```
+//!
+//! The path is used as given, so a path that does not lead to the crate is an error:
+//! ```compile_fail
+//! use downcast_trait::DowncastTrait;
+//! trait Container {}
+//! #[derive(DowncastTrait)]
+//! #[downcast(crate = widgets)]
+//! #[downcast(dyn Container)]
+//! struct Window;
+//! impl Container for Window {}
+//! ```
+//!
+//! Listing a trait that the type does not implement is reported at the listed trait:
+//! ```compile_fail
+//! use downcast_trait::DowncastTrait;
+//! trait Container {}
+//! #[derive(DowncastTrait)]
+//! #[downcast(dyn Container)]
+//! struct Label;
+//! ```
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, quote_spanned};
+use syn::{
+    parse::ParseStream, parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned,
+    DeriveInput, Error, GenericParam, Path, Token, Type, TypeTraitObject,
+};
+
+/// Implements `DowncastTrait` for a struct or enum, allowing it to be cast to every trait listed
+/// in its `#[downcast(dyn A, dyn B)]` attributes. The path of the downcast-trait crate can be set
+/// with `#[downcast(crate = path)]`.
+#[proc_macro_derive(DowncastTrait, attributes(downcast))]
+pub fn derive_downcast_trait(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The contents of one `#[downcast(...)]` attribute.
+enum Attribute {
+    /// `crate = path`, the path of the downcast-trait crate.
+    Crate(Path),
+    /// A list of trait objects to cast to.
+    Targets(Punctuated<Type, Token![,]>),
+}
+
+impl Attribute {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(Token![crate]) && input.peek2(Token![=]) {
+            input.parse::<Token![crate]>()?;
+            input.parse::<Token![=]>()?;
+            let path = input.call(Path::parse_mod_style)?;
+            input.parse::<Option<Token![,]>>()?;
+            Ok(Attribute::Crate(path))
+        } else {
+            Punctuated::parse_terminated(input).map(Attribute::Targets)
+        }
+    }
+}
+
+/// Collects the crate path and the trait objects listed in all `#[downcast(...)]` attributes.
+fn parse_attributes(input: &DeriveInput) -> syn::Result<(Path, Vec<TypeTraitObject>)> {
+    let mut krate = None;
+    let mut targets = Vec::new();
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("downcast"))
+    {
+        let list = match attr.parse_args_with(Attribute::parse)? {
+            Attribute::Crate(path) => {
+                if krate.is_some() {
+                    return Err(Error::new_spanned(path, "the crate path is already set"));
+                }
+                krate = Some(path);
+                continue;
+            }
+            Attribute::Targets(list) => list,
+        };
+        for ty in list {
+            match ty {
+                Type::TraitObject(target) if target.dyn_token.is_some() => targets.push(target),
+                other => {
+                    return Err(Error::new_spanned(
+                        other,
+                        "expected a trait object type such as `dyn Container`",
+                    ))
+                }
+            }
+        }
+    }
+    if targets.is_empty() {
+        return Err(Error::new(
+            Span::call_site(),
+            "expected at least one trait in a `#[downcast(dyn Trait, ...)]` attribute",
+        ));
+    }
+    Ok((
+        krate.unwrap_or_else(|| parse_quote!(::downcast_trait)),
+        targets,
+    ))
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let (krate, targets) = parse_attributes(&input)?;
+    let name = &input.ident;
+
+    // The casts produce `'static` trait objects, so every type parameter has to be `'static`.
+    let mut generics = input.generics.clone();
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Type(param) => {
+                let ident = &param.ident;
+                generics
+                    .make_where_clause()
+                    .predicates
+                    .push(parse_quote!(#ident: 'static));
+            }
+            GenericParam::Lifetime(param) => {
+                return Err(Error::new_spanned(
+                    param,
+                    "DowncastTrait can not be derived for types with lifetime parameters",
+                ))
+            }
+            GenericParam::Const(_) => {}
+        }
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    // One coercion per listed trait, spanned at the trait so a missing implementation is reported
+    // at the attribute instead of inside the generated code.
+    let assertions = targets.iter().map(|target| {
+        let span = target
+            .bounds
+            .first()
+            .map_or_else(|| target.span(), |bound| bound.span());
+        quote_spanned! {span=>
+            let _: &(#target) = value;
+        }
+    });
+
+    Ok(quote! {
+        const _: () = {
+            #[allow(dead_code, unused_parens)]
+            fn assert_implemented #impl_generics (value: &#name #ty_generics) #where_clause {
+                #(#assertions)*
+            }
+            impl #impl_generics #krate::DowncastTrait for #name #ty_generics #where_clause {
+                #krate::downcast_trait_impl_convert_to!(#(#targets),*);
+            }
+        };
+    })
+}
@@ -0,0 +1,120 @@
+extern crate downcast_trait as renamed;
+
+use downcast_trait::{downcast_trait, downcast_trait_box, downcast_trait_mut, DowncastTrait};
+use std::fmt::Debug;
+
+trait Container {
+    fn len(&self) -> usize;
+}
+trait Scrollable {
+    fn scroll(&mut self, by: i32);
+    fn position(&self) -> i32;
+}
+
+#[derive(DowncastTrait)]
+#[downcast(dyn Container, dyn Scrollable)]
+struct Window {
+    children: usize,
+    position: i32,
+}
+impl Container for Window {
+    fn len(&self) -> usize {
+        self.children
+    }
+}
+impl Scrollable for Window {
+    fn scroll(&mut self, by: i32) {
+        self.position += by;
+    }
+    fn position(&self) -> i32 {
+        self.position
+    }
+}
+
+#[derive(DowncastTrait)]
+#[downcast(dyn Container)]
+#[downcast(dyn Debug)]
+struct List<T>
+where
+    T: Debug,
+{
+    items: Vec<T>,
+}
+impl<T: Debug> Container for List<T> {
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+impl<T: Debug> Debug for List<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(&self.items).finish()
+    }
+}
+
+#[derive(DowncastTrait)]
+#[downcast(dyn Container + Send + Sync)]
+struct Shared {
+    children: usize,
+}
+impl Container for Shared {
+    fn len(&self) -> usize {
+        self.children
+    }
+}
+
+#[derive(renamed::DowncastTrait)]
+#[downcast(crate = renamed)]
+#[downcast(dyn Container)]
+struct Renamed;
+impl Container for Renamed {
+    fn len(&self) -> usize {
+        1
+    }
+}
+
+#[test]
+fn derived_casts() {
+    let mut window = Window {
+        children: 2,
+        position: 0,
+    };
+    let container = downcast_trait!(dyn Container, window.to_downcast_trait()).unwrap();
+    assert_eq!(container.len(), 2);
+    let scrollable = downcast_trait_mut!(dyn Scrollable, window.to_downcast_trait_mut()).unwrap();
+    scrollable.scroll(5);
+    assert_eq!(window.position, 5);
+    assert!(downcast_trait!(dyn Debug, window.to_downcast_trait()).is_none());
+
+    let boxed: Box<dyn DowncastTrait> = Box::new(window);
+    let scrollable = downcast_trait_box!(dyn Scrollable, boxed).ok().unwrap();
+    assert_eq!(scrollable.position(), 5);
+}
+
+#[test]
+fn derived_generic_casts() {
+    let list = List {
+        items: vec!["a", "b"],
+    };
+    let container = downcast_trait!(dyn Container, list.to_downcast_trait()).unwrap();
+    assert_eq!(container.len(), 2);
+    let debug = downcast_trait!(dyn Debug, list.to_downcast_trait()).unwrap();
+    assert_eq!(format!("{:?}", debug), r#"["a", "b"]"#);
+}
+
+#[test]
+fn derived_bounded_casts() {
+    let boxed: Box<dyn DowncastTrait> = Box::new(Shared { children: 3 });
+    assert!(downcast_trait!(dyn Container, boxed.as_ref()).is_some());
+    let container = downcast_trait_box!(dyn Container + Send + Sync, boxed)
+        .ok()
+        .unwrap();
+    let len = std::thread::spawn(move || container.len()).join().unwrap();
+    assert_eq!(len, 3);
+}
+
+#[test]
+fn derived_with_crate_path() {
+    let object = renamed::DowncastTrait::to_downcast_trait(&Renamed);
+    let container = renamed::downcast_trait!(dyn Container, object).unwrap();
+    assert_eq!(container.len(), 1);
+}
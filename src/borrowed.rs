@@ -0,0 +1,508 @@
+//! Casts between trait objects with a lifetime parameter, for types that borrow their data.
+//!
+//! `TypeId` only exists for `'static` types, so a trait object such as `dyn Expr<'a>` is instead
+//! identified by a key type given by [EraseLifetime]. The casts are done through
+//! [BorrowedDowncastTrait], which carries the lifetime as a parameter, so the returned trait
+//! object has exactly the lifetime of the object it was cast from.
+//!
+//! This is a separate and smaller API than [DowncastTrait](../trait.DowncastTrait.html), not a
+//! generalization of it. A `dyn DowncastTrait` is `'static`, and its slots, registry and
+//! descriptors are all keyed by `TypeId`, so giving it a lifetime parameter would change every
+//! implementation. The two traits can be implemented side by side, but an object of one can not
+//! be cast to the other.
+#[cfg(feature = "alloc")]
+use crate::Provided;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+use core::{any::TypeId, marker::PhantomData, ptr::NonNull};
+
+/// Maps a type with the lifetime `'a` to a `'static` key type identifying it.
+///
+/// This trait is normally implemented with the
+/// [downcast_trait_erase_lifetime](macro.downcast_trait_erase_lifetime.html) macro, which
+/// creates a new key type for every implementation.
+///
+/// # Safety
+/// `Key` must not be the key of any other type implementing this trait, and for each `'a` only a
+/// single type may implement `EraseLifetime<'a>` through this implementation.
+pub unsafe trait EraseLifetime<'a> {
+    /// The type whose `TypeId` identifies `Self` in a [BorrowedTraitSlot].
+    type Key: ?Sized + 'static;
+}
+
+unsafe impl<'a> EraseLifetime<'a> for dyn core::fmt::Debug + 'a {
+    type Key = dyn core::fmt::Debug;
+}
+
+unsafe impl<'a> EraseLifetime<'a> for dyn core::fmt::Display + 'a {
+    type Key = dyn core::fmt::Display;
+}
+
+/// The kind of cast a [BorrowedTraitSlot] was created for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Ref,
+    Mut,
+    #[cfg(feature = "alloc")]
+    Box,
+}
+
+/// An erased slot that receives a trait object with the lifetime `'a`, keyed by the
+/// [EraseLifetime] key of the requested trait.
+///
+/// The slot is invariant in `'a`, so an implementation of
+/// [BorrowedDowncastTrait](trait.BorrowedDowncastTrait.html) can only provide trait objects with
+/// the same lifetime as the object that is cast. The lifetime `'s` is the borrow of that object in
+/// a shared or mutable cast, and only references living as long can be provided.
+pub struct BorrowedTraitSlot<'s, 'a> {
+    target: TypeId,
+    kind: Kind,
+    out: NonNull<()>,
+    filled: bool,
+    _out: PhantomData<&'s mut ()>,
+    _lifetime: PhantomData<fn(&'a ()) -> &'a ()>,
+}
+
+impl<'s, 'a> BorrowedTraitSlot<'s, 'a> {
+    fn new<T: ?Sized + EraseLifetime<'a>, O>(kind: Kind, out: &'s mut Option<O>) -> Self {
+        BorrowedTraitSlot {
+            target: TypeId::of::<T::Key>(),
+            kind,
+            out: NonNull::from(out).cast(),
+            filled: false,
+            _out: PhantomData,
+            _lifetime: PhantomData,
+        }
+    }
+
+    /// The `TypeId` of the key of the trait object type that is requested.
+    pub fn target(&self) -> TypeId {
+        self.target
+    }
+
+    /// Returns true if `T` is the trait object type that is requested.
+    pub fn requests<T: ?Sized + EraseLifetime<'a>>(&self) -> bool {
+        self.target == TypeId::of::<T::Key>()
+    }
+
+    /// Returns true if one of the `provide_*` functions has stored a value in the slot.
+    pub fn is_filled(&self) -> bool {
+        self.filled
+    }
+
+    /// Returns the caller's output if the slot was created for a cast of `kind` to `T`.
+    fn out<T: ?Sized + EraseLifetime<'a>, O>(&mut self, kind: Kind) -> Option<&mut Option<O>> {
+        if self.kind == kind && self.requests::<T>() {
+            self.filled = true;
+            // Safety: the slot was created by `new::<U, O'>` with the same kind, where `U` has the
+            // same key as `T` and implements `EraseLifetime<'a>` for the same `'a`, so `U` is `T`
+            // and the output has the type `Option<O>`.
+            Some(unsafe { &mut *self.out.cast::<Option<O>>().as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Stores a shared reference in a slot created by a shared cast. Does nothing if `T` is not
+    /// the requested type, or if the slot was created for a mutable or boxed cast.
+    pub fn provide_ref<T: ?Sized + EraseLifetime<'a>>(&mut self, value: &'s T) {
+        if let Some(out) = self.out::<T, NonNull<T>>(Kind::Ref) {
+            *out = Some(NonNull::from(value));
+        }
+    }
+
+    /// Stores a mutable reference in a slot created by a mutable cast. Does nothing if `T` is not
+    /// the requested type, or if the slot was created for a shared or boxed cast.
+    pub fn provide_mut<T: ?Sized + EraseLifetime<'a>>(&mut self, value: &'s mut T) {
+        if let Some(out) = self.out::<T, NonNull<T>>(Kind::Mut) {
+            *out = Some(NonNull::from(value));
+        }
+    }
+
+    /// Stores a box in a slot created by a boxed cast, after converting it with `unsize`, which
+    /// is usually the unsizing coercion `|value| value`. The box is handed back unchanged if `T`
+    /// is not the requested type, or if the slot was created for a shared or mutable cast.
+    #[cfg(feature = "alloc")]
+    pub fn provide_box<T: ?Sized + EraseLifetime<'a>, S: ?Sized>(
+        &mut self,
+        value: Box<S>,
+        unsize: impl FnOnce(Box<S>) -> Box<T>,
+    ) -> Result<Provided<'s>, Box<S>> {
+        match self.out::<T, Box<T>>(Kind::Box) {
+            Some(out) => {
+                *out = Some(unsize(value));
+                Ok(Provided::new())
+            }
+            None => Err(value),
+        }
+    }
+}
+
+/// This trait is the counterpart of [DowncastTrait](trait.DowncastTrait.html) for types with a
+/// lifetime parameter, such as nodes of a syntax tree that borrow the parsed source. The functions
+/// required by this trait should be implemented using the
+/// [downcast_trait_impl_convert_to_borrowed](macro.downcast_trait_impl_convert_to_borrowed.html)
+/// macro, and the traits it is cast to need an [EraseLifetime] implementation from
+/// [downcast_trait_erase_lifetime](macro.downcast_trait_erase_lifetime.html):
+/// ```
+/// use downcast_trait::{
+///     downcast_trait_erase_lifetime, downcast_trait_impl_convert_to_borrowed,
+///     BorrowedDowncastTrait,
+/// };
+/// trait Node<'a>: BorrowedDowncastTrait<'a> {}
+/// trait Expr<'a>: 'a {
+///     fn source(&self) -> &'a str;
+/// }
+/// downcast_trait_erase_lifetime!(lifetime = 'a, dyn Expr<'a>);
+///
+/// struct Literal<'a> {
+///     source: &'a str,
+/// }
+/// impl<'a> Node<'a> for Literal<'a> {}
+/// impl<'a> Expr<'a> for Literal<'a> {
+///     fn source(&self) -> &'a str {
+///         self.source
+///     }
+/// }
+/// impl<'a> BorrowedDowncastTrait<'a> for Literal<'a> {
+///     downcast_trait_impl_convert_to_borrowed!(lifetime = 'a, dyn Expr<'a>);
+/// }
+///
+/// fn source_of<'a>(node: &dyn Node<'a>) -> Option<&'a str> {
+///     let expr = node.to_borrowed_downcast_trait().cast_ref::<dyn Expr<'a>>()?;
+///     Some(expr.source())
+/// }
+/// let text = String::from("1 + 2");
+/// let literal = Literal { source: &text[..1] };
+/// assert_eq!(source_of(&literal), Some("1"));
+/// ```
+///
+/// The result keeps the lifetime of the source, so it can not outlive the borrowed data:
+/// ```compile_fail
+/// # use downcast_trait::{
+/// #     downcast_trait_erase_lifetime, downcast_trait_impl_convert_to_borrowed,
+/// #     BorrowedDowncastTrait,
+/// # };
+/// # trait Expr<'a>: 'a {}
+/// # downcast_trait_erase_lifetime!(lifetime = 'a, dyn Expr<'a>);
+/// # struct Literal<'a>(&'a str);
+/// # impl<'a> Expr<'a> for Literal<'a> {}
+/// # impl<'a> BorrowedDowncastTrait<'a> for Literal<'a> {
+/// #     downcast_trait_impl_convert_to_borrowed!(lifetime = 'a, dyn Expr<'a>);
+/// # }
+/// let expr: Box<dyn Expr<'static>> = {
+///     let text = String::from("1");
+///     let literal: Box<dyn BorrowedDowncastTrait<'_>> = Box::new(Literal(&text));
+///     literal.cast_box::<dyn Expr<'_>>().ok().unwrap()
+/// };
+/// ```
+///
+/// Only shared, mutable and boxed casts are supported, and only to the listed trait objects: a
+/// target listed as `dyn Expr<'a> + Send` is not also answered as `dyn Expr<'a>`. There are no
+/// delegated or projected targets, no pinned, `Rc`, `Arc` or pointer casts, no
+/// `supported_traits`, no derive and no registry.
+pub trait BorrowedDowncastTrait<'a>: 'a {
+    /// This function is called by
+    /// [cast_ref](trait.BorrowedDowncastTrait.html#method.cast_ref) and should not be accessed
+    /// directly.
+    fn convert_to_trait<'s>(&'s self, slot: &mut BorrowedTraitSlot<'s, 'a>);
+    /// This function is called by
+    /// [cast_mut](trait.BorrowedDowncastTrait.html#method.cast_mut) and should not be accessed
+    /// directly.
+    fn convert_to_trait_mut<'s>(&'s mut self, slot: &mut BorrowedTraitSlot<'s, 'a>);
+    /// This function is called by
+    /// [cast_box](trait.BorrowedDowncastTrait.html#method.cast_box) and should not be accessed
+    /// directly. It returns the [Provided](struct.Provided.html) proof of the slot after providing
+    /// the box to it, and hands the box back as `Err` if the requested trait is not supported.
+    #[cfg(feature = "alloc")]
+    fn convert_to_trait_box<'s>(
+        self: Box<Self>,
+        slot: &mut BorrowedTraitSlot<'s, 'a>,
+    ) -> Result<Provided<'s>, Box<dyn BorrowedDowncastTrait<'a>>>;
+    /// This function is used to cast any implementer of this trait to a BorrowedDowncastTrait
+    fn to_borrowed_downcast_trait(&self) -> &dyn BorrowedDowncastTrait<'a>;
+    /// This function is used to cast any implementer of this trait to a mut BorrowedDowncastTrait
+    fn to_borrowed_downcast_trait_mut(&mut self) -> &mut dyn BorrowedDowncastTrait<'a>;
+    /// This function is used to cast any implementer of this trait to a
+    /// `Box<BorrowedDowncastTrait>`
+    #[cfg(feature = "alloc")]
+    fn to_borrowed_downcast_trait_box(self: Box<Self>) -> Box<dyn BorrowedDowncastTrait<'a>>;
+}
+
+impl<'a> dyn BorrowedDowncastTrait<'a> + '_ {
+    /// Casts this object to a reference to the trait object type `T`, e.g.
+    /// `obj.cast_ref::<dyn Expr<'a>>()`. Returns None if the trait is not supported.
+    pub fn cast_ref<T: ?Sized + EraseLifetime<'a>>(&self) -> Option<&T> {
+        let mut out = None::<NonNull<T>>;
+        self.convert_to_trait(&mut BorrowedTraitSlot::new::<T, _>(Kind::Ref, &mut out));
+        // Safety: the slot only accepts references living as long as the reborrow of self it was
+        // passed with. The implementation is generic over that lifetime, so the reference is
+        // valid for the whole borrow of self.
+        out.map(|ptr| unsafe { &*ptr.as_ptr() })
+    }
+
+    /// Casts this object to a mutable reference to the trait object type `T`, e.g.
+    /// `obj.cast_mut::<dyn Expr<'a>>()`. Returns None if the trait is not supported.
+    pub fn cast_mut<T: ?Sized + EraseLifetime<'a>>(&mut self) -> Option<&mut T> {
+        let mut out = None::<NonNull<T>>;
+        self.convert_to_trait_mut(&mut BorrowedTraitSlot::new::<T, _>(Kind::Mut, &mut out));
+        // Safety: as in cast_ref, the reference is valid for the whole borrow of self, which stays
+        // mutably borrowed for as long as the result and is not used again by this function.
+        out.map(|ptr| unsafe { &mut *ptr.as_ptr() })
+    }
+}
+
+impl<'a> dyn BorrowedDowncastTrait<'a> {
+    /// Casts this boxed object to a box of the trait object type `T`, e.g.
+    /// `obj.cast_box::<dyn Expr<'a>>()`. Returns the original box as `Err` if the trait is not
+    /// supported.
+    #[cfg(feature = "alloc")]
+    pub fn cast_box<T: ?Sized + EraseLifetime<'a>>(self: Box<Self>) -> Result<Box<T>, Box<Self>> {
+        let mut out = None::<Box<T>>;
+        let _provided =
+            self.convert_to_trait_box(&mut BorrowedTraitSlot::new::<T, _>(Kind::Box, &mut out))?;
+        Ok(out.unwrap_or_else(|| unreachable!("a slot is filled when it hands out a Provided")))
+    }
+}
+
+/// This macro implements [EraseLifetime](trait.EraseLifetime.html) for trait objects with a
+/// lifetime parameter, so they can be used as targets of
+/// [downcast_trait_impl_convert_to_borrowed](macro.downcast_trait_impl_convert_to_borrowed.html)
+/// e.g:
+/// ```ignore
+/// downcast_trait_erase_lifetime!(lifetime = 'a, dyn Expr<'a>, dyn Stmt<'a>);
+/// ```
+/// The lifetime is named first, and each trait object may add auto trait bounds such as
+/// `dyn Expr<'a> + Send`. The implementations are for the trait objects with the bound `+ 'a`
+/// added, which is what `dyn Expr<'a>` means if the trait is declared as `trait Expr<'a>: 'a`.
+#[macro_export]
+macro_rules! downcast_trait_erase_lifetime {
+    (lifetime = $lt:lifetime, $($targets:tt)+) => {
+        $crate::__downcast_trait_split_list!(
+            @munch __downcast_trait_erase_lifetime $lt [] [] [] $($targets)+
+        );
+    };
+}
+
+/// This macro is used internally by
+/// [downcast_trait_erase_lifetime](macro.downcast_trait_erase_lifetime.html) to implement
+/// [EraseLifetime](trait.EraseLifetime.html) for the split targets.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_erase_lifetime {
+    ($lt:lifetime $({$($target:tt)+})+) => {
+        $(
+        const _: () = {
+            pub struct Key;
+            unsafe impl<$lt> $crate::EraseLifetime<$lt> for $($target)+ + $lt {
+                type Key = Key;
+            }
+        };
+        )+
+    };
+}
+
+/// This macro can be used by an impl of [BorrowedDowncastTrait](trait.BorrowedDowncastTrait.html)
+/// to implement the functions required to cast to one or more traits with the lifetime of the
+/// implementation e.g:
+/// ```ignore
+/// impl<'a> BorrowedDowncastTrait<'a> for Literal<'a> {
+///     downcast_trait_impl_convert_to_borrowed!(lifetime = 'a, dyn Expr<'a> + Send, dyn Debug);
+/// }
+/// ```
+/// The lifetime must be the lifetime parameter of the implemented `BorrowedDowncastTrait<'a>`,
+/// and is added as a bound to each target. Targets are answered exactly as listed.
+#[macro_export]
+macro_rules! downcast_trait_impl_convert_to_borrowed {
+    (lifetime = $lt:lifetime, $($targets:tt)+) => {
+        $crate::__downcast_trait_split_list!(
+            @munch __downcast_trait_impl_convert_to_borrowed $lt [] [] [] $($targets)+
+        );
+    };
+}
+
+/// This macro is used internally by
+/// [downcast_trait_impl_convert_to_borrowed](macro.downcast_trait_impl_convert_to_borrowed.html)
+/// to implement the functions for the split targets.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_impl_convert_to_borrowed {
+    ($lt:lifetime $({$($target:tt)+})+) => {
+        fn convert_to_trait<'__slot>(&'__slot self, slot: &mut $crate::BorrowedTraitSlot<'__slot, $lt>) {
+            $(
+            if slot.requests::<$($target)+ + $lt>() {
+                return slot.provide_ref::<$($target)+ + $lt>(self);
+            }
+            )+
+        }
+        fn convert_to_trait_mut<'__slot>(
+            &'__slot mut self,
+            slot: &mut $crate::BorrowedTraitSlot<'__slot, $lt>,
+        ) {
+            $(
+            if slot.requests::<$($target)+ + $lt>() {
+                return slot.provide_mut::<$($target)+ + $lt>(self);
+            }
+            )+
+        }
+        fn to_borrowed_downcast_trait(&self) -> &dyn $crate::BorrowedDowncastTrait<$lt> {
+            self
+        }
+        fn to_borrowed_downcast_trait_mut(&mut self) -> &mut dyn $crate::BorrowedDowncastTrait<$lt> {
+            self
+        }
+        $crate::__downcast_trait_impl_convert_to_borrowed_box!($lt $({$($target)+})+);
+    };
+}
+
+/// This macro is used internally by
+/// [downcast_trait_impl_convert_to_borrowed](macro.downcast_trait_impl_convert_to_borrowed.html)
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "alloc")]
+macro_rules! __downcast_trait_impl_convert_to_borrowed_box {
+    ($lt:lifetime $({$($target:tt)+})+) => {
+        fn convert_to_trait_box<'__slot>(
+            self: $crate::__private::Box<Self>,
+            slot: &mut $crate::BorrowedTraitSlot<'__slot, $lt>,
+        ) -> $crate::__private::Result<
+            $crate::Provided<'__slot>,
+            $crate::__private::Box<dyn $crate::BorrowedDowncastTrait<$lt>>,
+        > {
+            $(
+            if slot.requests::<$($target)+ + $lt>() {
+                return match slot.provide_box::<$($target)+ + $lt, _>(self, |value| value) {
+                    $crate::__private::Ok(provided) => $crate::__private::Ok(provided),
+                    $crate::__private::Err(this) => $crate::__private::Err(this),
+                };
+            }
+            )+
+            $crate::__private::Err(self)
+        }
+        fn to_borrowed_downcast_trait_box(
+            self: $crate::__private::Box<Self>,
+        ) -> $crate::__private::Box<dyn $crate::BorrowedDowncastTrait<$lt>> {
+            self
+        }
+    };
+}
+
+/// This macro is used internally by
+/// [downcast_trait_impl_convert_to_borrowed](macro.downcast_trait_impl_convert_to_borrowed.html)
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "alloc"))]
+macro_rules! __downcast_trait_impl_convert_to_borrowed_box {
+    ($lt:lifetime $($targets:tt)+) => {};
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    #[cfg(feature = "alloc")]
+    use std::boxed::Box;
+    use std::vec;
+
+    trait Node<'a>: BorrowedDowncastTrait<'a> {
+        fn kind(&self) -> &'static str;
+    }
+    trait Expr<'a>: 'a {
+        fn source(&self) -> &'a [u8];
+        fn shrink(&mut self);
+    }
+    trait Stmt<'a>: 'a {}
+    trait Slice<'a, T: 'a>: 'a {
+        fn slice(&self) -> &'a [T];
+    }
+    downcast_trait_erase_lifetime!(
+        lifetime = 'a,
+        dyn Expr<'a>,
+        dyn Expr<'a> + Send,
+        dyn Stmt<'a>,
+        dyn Slice<'a, u8>,
+    );
+
+    #[derive(Debug)]
+    struct View<'a> {
+        data: &'a [u8],
+    }
+    impl<'a> Node<'a> for View<'a> {
+        fn kind(&self) -> &'static str {
+            "view"
+        }
+    }
+    impl<'a> Expr<'a> for View<'a> {
+        fn source(&self) -> &'a [u8] {
+            self.data
+        }
+        fn shrink(&mut self) {
+            self.data = &self.data[1..];
+        }
+    }
+    impl<'a> Slice<'a, u8> for View<'a> {
+        fn slice(&self) -> &'a [u8] {
+            self.data
+        }
+    }
+    impl<'a> BorrowedDowncastTrait<'a> for View<'a> {
+        downcast_trait_impl_convert_to_borrowed!(
+            lifetime = 'a,
+            dyn Expr<'a>,
+            dyn Slice<'a, u8>,
+            dyn core::fmt::Debug
+        );
+    }
+
+    struct Shared<'a> {
+        data: &'a [u8],
+    }
+    impl<'a> Expr<'a> for Shared<'a> {
+        fn source(&self) -> &'a [u8] {
+            self.data
+        }
+        fn shrink(&mut self) {}
+    }
+    impl<'a> BorrowedDowncastTrait<'a> for Shared<'a> {
+        downcast_trait_impl_convert_to_borrowed!(lifetime = 'a, dyn Expr<'a> + Send);
+    }
+
+    #[test]
+    fn borrowed_casts() {
+        let data = vec![1, 2, 3];
+        let mut view = View { data: &data };
+        let node: &dyn Node<'_> = &view;
+        assert_eq!(node.kind(), "view");
+        let object = node.to_borrowed_downcast_trait();
+        let source = object.cast_ref::<dyn Expr<'_>>().map(|e| e.source());
+        assert_eq!(source, Some(&data[..]));
+        assert!(object.cast_ref::<dyn Stmt<'_>>().is_none());
+        assert!(object.cast_ref::<dyn core::fmt::Debug>().is_some());
+        let slice = object.cast_ref::<dyn Slice<'_, u8>>().map(|s| s.slice());
+        assert_eq!(slice, Some(&data[..]));
+
+        let object = view.to_borrowed_downcast_trait_mut();
+        object.cast_mut::<dyn Expr<'_>>().expect("cast should succeed").shrink();
+        assert!(object.cast_mut::<dyn Stmt<'_>>().is_none());
+        assert_eq!(view.data, &data[1..]);
+
+        // Only the listed combination of bounds is answered.
+        let shared = Shared { data: &data };
+        let object = shared.to_borrowed_downcast_trait();
+        let sent = object.cast_ref::<dyn Expr<'_> + Send>();
+        assert_eq!(sent.map(|e| e.source()), Some(&data[..]));
+        assert!(object.cast_ref::<dyn Expr<'_>>().is_none());
+
+        #[cfg(feature = "alloc")]
+        {
+            let boxed: Box<dyn BorrowedDowncastTrait<'_>> = Box::new(View { data: &data });
+            let Err(boxed) = boxed.cast_box::<dyn Stmt<'_>>() else {
+                panic!("cast should fail")
+            };
+            let Ok(expr) = boxed.cast_box::<dyn Expr<'_>>() else {
+                panic!("cast should succeed")
+            };
+            assert_eq!(expr.source(), &data[..]);
+        }
+    }
+}
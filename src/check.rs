@@ -0,0 +1,220 @@
+//! Test time checks that a cast list matches the traits a type implements.
+use core::any::TypeId;
+
+use crate::TraitDescriptor;
+
+/// One of the candidate traits given to
+/// [assert_cast_list_complete](../macro.assert_cast_list_complete.html).
+#[doc(hidden)]
+pub struct Candidate {
+    pub type_id: TypeId,
+    pub type_name: &'static str,
+    pub implemented: bool,
+}
+
+/// Panics with a message naming every candidate that is implemented by `type_name` but missing
+/// from `listed`, and every entry of `listed` that is not one of the candidates.
+///
+/// An entry with bounds, such as `dyn Container + Send`, also covers the candidate
+/// `dyn Container`. Entries projected to a field are answered by the field, so they are never
+/// reported as unused.
+#[doc(hidden)]
+pub fn check_cast_list(type_name: &str, listed: &[TraitDescriptor], candidates: &[Candidate]) {
+    let missing: Vec<_> = candidates
+        .iter()
+        .filter(|candidate| candidate.implemented)
+        .filter(|candidate| {
+            !listed
+                .iter()
+                .any(|d| d.type_id() == candidate.type_id || d.unbounded == candidate.type_id)
+        })
+        .map(|candidate| candidate.type_name)
+        .collect();
+    let unused: Vec<_> = listed
+        .iter()
+        .filter(|d| !d.projected)
+        .filter(|d| {
+            !candidates.iter().any(|candidate| {
+                candidate.implemented
+                    && (candidate.type_id == d.type_id() || candidate.type_id == d.unbounded)
+            })
+        })
+        .map(|d| d.type_name())
+        .collect();
+    let mut problems = Vec::new();
+    if !missing.is_empty() {
+        problems.push(format!(
+            "implements but does not list {}",
+            missing.join(", ")
+        ));
+    }
+    if !unused.is_empty() {
+        problems.push(format!(
+            "lists {} which is not an implemented candidate",
+            unused.join(", ")
+        ));
+    }
+    if !problems.is_empty() {
+        panic!("the cast list of {} {}", type_name, problems.join(", and "));
+    }
+}
+
+/// This macro checks in a test that the cast list of a type contains exactly the traits it
+/// implements among a set of candidates. It fails with a message naming every candidate the type
+/// implements but does not list, and every listed trait that is not an implemented candidate:
+/// ```should_panic
+/// use downcast_trait::prelude::*;
+/// trait Container {}
+/// trait Scrollable {}
+/// trait Clickable {}
+/// struct Window;
+/// impl Container for Window {}
+/// impl Scrollable for Window {}
+/// impl DowncastTrait for Window {
+///     downcast_trait_impl_convert_to!(dyn Container);
+/// }
+/// // Panics: the cast list of Window implements but does not list dyn Scrollable
+/// assert_cast_list_complete!(Window; dyn Container, dyn Scrollable, dyn Clickable);
+/// ```
+///
+/// Candidates can have bounds, such as `dyn Container + Send`, which are then only implemented if
+/// the type also implements the bounds. An entry listed with bounds covers both its exact form and
+/// the trait without bounds. An entry projected to a field, such as `dyn Scrollable => self.area`,
+/// covers its trait, and is not reported as unused since the type itself need not implement it.
+///
+/// Whether the type implements a candidate is found by probing: a probe type has an inherent
+/// constant when the trait is implemented, which is preferred over the constant of a fallback
+/// trait. The type must therefore be concrete. Traits that are only reached through
+/// [downcast_trait_hierarchy](macro.downcast_trait_hierarchy.html) or a delegate do not count as
+/// listed.
+#[macro_export]
+macro_rules! assert_cast_list_complete
+{
+    ($type:ty; $($candidates:tt)+) => {
+        $crate::__downcast_trait_split_list!(
+            @munch __downcast_trait_check_candidates ($type) [] [] [] $($candidates)+
+        )
+    };
+}
+
+/// This macro is used internally by
+/// [assert_cast_list_complete](macro.assert_cast_list_complete.html) to probe the split candidates
+/// and check the cast list against them.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_check_candidates
+{
+    (($type:ty) $({dyn $($candidate:tt)+})+) => {{
+        #[allow(dead_code)]
+        struct __DowncastTraitProbe<S: ?Sized, T: ?Sized>(
+            $crate::__private::PhantomData<S>,
+            $crate::__private::PhantomData<T>,
+        );
+        trait __DowncastTraitNotImplemented {
+            const IMPLEMENTED: bool = false;
+        }
+        impl<S: ?Sized, T: ?Sized> __DowncastTraitNotImplemented for __DowncastTraitProbe<S, T> {}
+        $(
+        impl<S: ?Sized + $($candidate)+> __DowncastTraitProbe<S, dyn $($candidate)+> {
+            #[allow(dead_code)]
+            const IMPLEMENTED: bool = true;
+        }
+        )+
+        $crate::__private::check_cast_list(
+            $crate::__private::type_name::<$type>(),
+            <$type as $crate::DowncastTrait>::listed_traits(),
+            &[$(
+                $crate::__private::Candidate {
+                    type_id: $crate::__private::TypeId::of::<dyn $($candidate)+>(),
+                    type_name: $crate::__private::type_name::<dyn $($candidate)+>(),
+                    implemented: __DowncastTraitProbe::<$type, dyn $($candidate)+>::IMPLEMENTED,
+                }
+            ),+],
+        );
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{downcast_trait_impl_convert_to, DowncastTrait};
+    use core::fmt::{self, Debug, Display};
+
+    struct Complete;
+    impl Display for Complete {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "complete")
+        }
+    }
+    impl DowncastTrait for Complete {
+        downcast_trait_impl_convert_to!(dyn Display);
+    }
+
+    #[derive(Debug)]
+    struct Forgotten;
+    impl Display for Forgotten {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "forgotten")
+        }
+    }
+    impl DowncastTrait for Forgotten {
+        downcast_trait_impl_convert_to!(dyn Display);
+    }
+
+    struct Sendable;
+    impl Display for Sendable {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "sendable")
+        }
+    }
+    impl DowncastTrait for Sendable {
+        downcast_trait_impl_convert_to!(dyn Display + Send + Sync);
+    }
+
+    struct Labelled {
+        label: Complete,
+    }
+    impl Debug for Labelled {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "labelled {}", self.label)
+        }
+    }
+    impl DowncastTrait for Labelled {
+        downcast_trait_impl_convert_to!(dyn Debug, dyn Display + Send => self.label);
+    }
+
+    #[test]
+    fn complete_cast_list() {
+        assert_cast_list_complete!(Complete; dyn Display, dyn Debug);
+    }
+
+    #[test]
+    #[should_panic(expected = "implements but does not list dyn core::fmt::Debug")]
+    fn forgotten_cast() {
+        assert_cast_list_complete!(Forgotten; dyn Display, dyn Debug);
+    }
+
+    #[test]
+    #[should_panic(expected = "lists dyn core::fmt::Display which is not an implemented candidate")]
+    fn unused_cast() {
+        assert_cast_list_complete!(Complete; dyn Debug);
+    }
+
+    #[test]
+    fn bounded_cast_list() {
+        assert_cast_list_complete!(Sendable; dyn Display, dyn Debug);
+        assert_cast_list_complete!(Sendable; dyn Display + Send + Sync, dyn Debug);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "implements but does not list dyn core::fmt::Display + core::marker::Send"
+    )]
+    fn missing_bounded_cast() {
+        assert_cast_list_complete!(Complete; dyn Display + Send, dyn Debug + Sync);
+    }
+
+    #[test]
+    fn projected_cast_list() {
+        assert_cast_list_complete!(Labelled; dyn Debug, dyn Display);
+    }
+}
@@ -0,0 +1,275 @@
+//! An object assembled at runtime from parts, each providing one trait.
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    any::{Any, TypeId},
+    pin::Pin,
+};
+
+use crate::{DowncastTrait, Provided, TraitDescriptor, TraitSlot};
+
+/// A part of a [Composite], holding the trait object it was registered as.
+struct Part<T: ?Sized>(Box<T>);
+
+/// The operations on a part with the trait object type erased, so parts can share a list.
+trait ErasedPart {
+    fn descriptor(&self) -> TraitDescriptor;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn provide_ref<'s>(&'s self, slot: &mut TraitSlot<'s>);
+    fn provide_mut<'s>(&'s mut self, slot: &mut TraitSlot<'s>);
+    fn provide_box<'s>(
+        self: Box<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Box<dyn ErasedPart>>;
+}
+
+impl<T: ?Sized + 'static> ErasedPart for Part<T> {
+    fn descriptor(&self) -> TraitDescriptor {
+        TraitDescriptor::new::<T>(true, true, true)
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn provide_ref<'s>(&'s self, slot: &mut TraitSlot<'s>) {
+        slot.provide_ref::<T>(&self.0)
+    }
+
+    fn provide_mut<'s>(&'s mut self, slot: &mut TraitSlot<'s>) {
+        slot.provide_mut::<T>(&mut self.0)
+    }
+
+    fn provide_box<'s>(
+        self: Box<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Box<dyn ErasedPart>> {
+        match slot.provide_box::<T, _>(self.0, |part| part) {
+            Ok(provided) => Ok(provided),
+            Err(part) => Err(Box::new(Part(part))),
+        }
+    }
+}
+
+/// A [DowncastTrait](trait.DowncastTrait.html) object built at runtime out of independent parts.
+/// Each part is registered as one trait object type, and casts to that type are answered with the
+/// part:
+/// ```
+/// # use downcast_trait::{downcast_trait, downcast_trait_mut, Composite, DowncastTrait};
+/// trait Clickable {
+///     fn click(&mut self) -> u32;
+/// }
+/// trait Render {
+///     fn render(&self) -> String;
+/// }
+/// struct Counter(u32);
+/// impl Clickable for Counter {
+///     fn click(&mut self) -> u32 {
+///         self.0 += 1;
+///         self.0
+///     }
+/// }
+/// struct Label;
+/// impl Render for Label {
+///     fn render(&self) -> String {
+///         "label".to_string()
+///     }
+/// }
+///
+/// let mut object = Composite::new()
+///     .with::<dyn Clickable>(Box::new(Counter(0)))
+///     .with::<dyn Render>(Box::new(Label));
+/// let render = downcast_trait!(dyn Render, object.to_downcast_trait()).unwrap();
+/// assert_eq!(render.render(), "label");
+/// let clickable = downcast_trait_mut!(dyn Clickable, object.to_downcast_trait_mut()).unwrap();
+/// assert_eq!(clickable.click(), 1);
+///
+/// let (clickable, render) = object.split_mut::<dyn Clickable, dyn Render>();
+/// assert_eq!(clickable.unwrap().click(), 2);
+/// assert_eq!(render.unwrap().render(), "label");
+/// ```
+///
+/// A boxed cast to a part takes the part out of the composite and drops the other parts. `Rc` and
+/// `Arc` casts can not move a part out of the shared composite, and only succeed for `Composite`
+/// itself, as do pinned casts. The parts are only known at runtime, so
+/// [supported_traits](trait.DowncastTrait.html#method.supported_traits) is empty, and
+/// [parts](#method.parts) describes them instead.
+#[derive(Default)]
+pub struct Composite {
+    parts: Vec<(TypeId, Box<dyn ErasedPart>)>,
+}
+
+impl Composite {
+    /// Creates a composite without any parts.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `part` as the provider of the trait object type `T`, replacing an earlier part
+    /// registered for the same type.
+    pub fn with<T: ?Sized + 'static>(mut self, part: Box<T>) -> Self {
+        let part: Box<dyn ErasedPart> = Box::new(Part(part));
+        match self
+            .parts
+            .iter_mut()
+            .find(|(id, _)| *id == TypeId::of::<T>())
+        {
+            Some((_, existing)) => *existing = part,
+            None => self.parts.push((TypeId::of::<T>(), part)),
+        }
+        self
+    }
+
+    /// Returns a description of the trait provided by each part, in the order they were added.
+    pub fn parts(&self) -> impl Iterator<Item = TraitDescriptor> + '_ {
+        self.parts.iter().map(|(_, part)| part.descriptor())
+    }
+
+    fn part(&self, target: TypeId) -> Option<&dyn ErasedPart> {
+        self.parts
+            .iter()
+            .find(|(id, _)| *id == target)
+            .map(|(_, part)| part.as_ref())
+    }
+
+    /// Borrows the parts providing `A` and `B` mutably at the same time. Either is `None` if no
+    /// part provides it, and `B` is `None` if it is the same type as `A`.
+    pub fn split_mut<A: ?Sized + 'static, B: ?Sized + 'static>(
+        &mut self,
+    ) -> (Option<&mut A>, Option<&mut B>) {
+        let mut first = None;
+        let mut second = None;
+        for (id, part) in self.parts.iter_mut() {
+            if *id == TypeId::of::<A>() {
+                first = part
+                    .as_any_mut()
+                    .downcast_mut::<Part<A>>()
+                    .map(|part| part.0.as_mut());
+            } else if *id == TypeId::of::<B>() {
+                second = part
+                    .as_any_mut()
+                    .downcast_mut::<Part<B>>()
+                    .map(|part| part.0.as_mut());
+            }
+        }
+        (first, second)
+    }
+}
+
+impl DowncastTrait for Composite {
+    fn convert_to_trait<'s>(&'s self, slot: &mut TraitSlot<'s>) {
+        if slot.target() == TypeId::of::<Self>() {
+            slot.provide_ref::<Self>(self);
+            slot.provide_ptr::<Self, Self>(self, |this| this)
+        } else if let Some(part) = self.part(slot.target()) {
+            part.provide_ref(slot)
+        }
+    }
+
+    fn convert_to_trait_mut<'s>(&'s mut self, slot: &mut TraitSlot<'s>) {
+        if slot.target() == TypeId::of::<Self>() {
+            slot.provide_mut::<Self>(self)
+        } else if let Some((_, part)) = self.parts.iter_mut().find(|(id, _)| *id == slot.target()) {
+            part.provide_mut(slot)
+        }
+    }
+
+    fn convert_to_trait_pin<'s>(self: Pin<&'s mut Self>, slot: &mut TraitSlot<'s>) {
+        // The parts can be replaced or moved out of an unpinned composite, so they are not
+        // structurally pinned and only the composite itself is answered.
+        if slot.target() == TypeId::of::<Self>() {
+            slot.provide_pin::<Self>(self)
+        }
+    }
+
+    fn convert_to_trait_box<'s>(
+        mut self: Box<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Box<dyn DowncastTrait>> {
+        if slot.target() == TypeId::of::<Self>() {
+            return match slot.provide_box::<Self, _>(self, |this| this) {
+                Ok(provided) => Ok(provided),
+                Err(this) => Err(this),
+            };
+        }
+        let Some(index) = self.parts.iter().position(|(id, _)| *id == slot.target()) else {
+            return Err(self);
+        };
+        let (id, part) = self.parts.swap_remove(index);
+        match part.provide_box(slot) {
+            Ok(provided) => Ok(provided),
+            Err(part) => {
+                // Put the part back where it was, so a refused cast leaves the composite as it was.
+                let last = self.parts.len();
+                self.parts.push((id, part));
+                self.parts.swap(index, last);
+                Err(self)
+            }
+        }
+    }
+
+    fn capability_mask(&self, _family: TypeId, members: &[TraitDescriptor]) -> Option<u64> {
+        // The parts differ between composites, so the mask is looked up every time, but without
+        // asking any part for a cast.
+        Some(crate::family::mask_of(members, |id| {
+            id == TypeId::of::<Self>() || self.part(id).is_some()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::{downcast_trait, downcast_trait_box, downcast_trait_mut};
+    use core::fmt::{Debug, Display};
+    use std::{boxed::Box, format, string::ToString, vec::Vec};
+
+    trait Counter {
+        fn add(&mut self, amount: u32) -> u32;
+    }
+    impl Counter for u32 {
+        fn add(&mut self, amount: u32) -> u32 {
+            *self += amount;
+            *self
+        }
+    }
+
+    #[test]
+    fn composite_parts() {
+        let mut composite = Composite::new()
+            .with::<dyn Counter>(Box::new(1u32))
+            .with::<dyn Display>(Box::new("shown"))
+            .with::<dyn Debug>(Box::new(1u8))
+            .with::<dyn Debug>(Box::new(2u8));
+        let object = composite.to_downcast_trait_mut();
+        assert_eq!(
+            downcast_trait!(dyn Display, object).unwrap().to_string(),
+            "shown"
+        );
+        assert_eq!(
+            format!("{:?}", downcast_trait!(dyn Debug, object).unwrap()),
+            "2"
+        );
+        assert_eq!(downcast_trait_mut!(dyn Counter, object).unwrap().add(2), 3);
+        assert!(object.is::<Composite>());
+        assert!(object.supported_traits().is_empty());
+
+        let names: Vec<_> = composite.parts().map(|part| part.type_name()).collect();
+        assert_eq!(names.len(), 3);
+        assert!(names[0].ends_with("Counter"));
+
+        let (counter, shown) = composite.split_mut::<dyn Counter, dyn Display>();
+        assert_eq!(counter.unwrap().add(1), 4);
+        assert_eq!(shown.unwrap().to_string(), "shown");
+        let (counter, same) = composite.split_mut::<dyn Counter, dyn Counter>();
+        assert!(counter.is_some() && same.is_none());
+
+        let boxed: Box<dyn DowncastTrait> = Box::new(composite);
+        let boxed = match downcast_trait_box!(dyn Iterator<Item = u8>, boxed) {
+            Ok(_) => panic!("no part provides Iterator"),
+            Err(boxed) => boxed,
+        };
+        let mut counter = downcast_trait_box!(dyn Counter, boxed).ok().unwrap();
+        assert_eq!(counter.add(1), 5);
+    }
+}
@@ -0,0 +1,488 @@
+//! Casts that are only available when a type parameter implements a trait.
+//!
+//! A generic type such as `Cell<T>` may implement `Display` only when `T: Display`. Stable Rust
+//! has no specialization, so an `impl<T> DowncastTrait for Cell<T>` can not find out by itself
+//! whether to answer a cast to `dyn Display`. The answer is instead looked up in [ConditionalCast],
+//! a helper trait implemented once for each type and conditional trait, which holds the
+//! [Coercions] of the type to the trait if it implements it.
+//!
+//! [downcast_trait_impl_for](../macro.downcast_trait_impl_for.html) implements `DowncastTrait` on
+//! top of it. For a list of concrete types it also implements `ConditionalCast`, choosing the
+//! coercions with a probe type that has an inherent item when the bound holds, and falls back to a
+//! trait item when it does not. Inherent items are preferred over trait items, so the probe answers
+//! for the concrete types only. Method resolution happens where the code is written, not per
+//! instantiation, so the probe can not tell the instances of a generic type apart, and a generic
+//! implementation leaves `ConditionalCast` to a blanket implementation or one for each type.
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, rc::Rc};
+#[cfg(not(feature = "alloc"))]
+use core::marker::PhantomData;
+use core::{pin::Pin, ptr::NonNull};
+
+use crate::TraitSlot;
+#[cfg(feature = "alloc")]
+use crate::{DowncastTrait, Provided};
+
+/// The unsizing coercions of `S` to the trait object type `T`, which are built with
+/// [downcast_trait_coercions](macro.downcast_trait_coercions.html) where `S` is known to implement
+/// the trait.
+pub struct Coercions<S, T: ?Sized + 'static> {
+    #[doc(hidden)]
+    pub by_ref: for<'a> fn(&'a S) -> &'a T,
+    #[doc(hidden)]
+    pub by_mut: for<'a> fn(&'a mut S) -> &'a mut T,
+    #[doc(hidden)]
+    pub pinned: for<'a> fn(Pin<&'a mut S>) -> Pin<&'a mut T>,
+    #[doc(hidden)]
+    pub ptr: fn(Pin<NonNull<S>>) -> Pin<NonNull<T>>,
+    #[doc(hidden)]
+    pub owned: OwnedCoercions<S, T>,
+}
+
+/// The coercions of the owning pointers to `S`, which only exist with the `alloc` feature.
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub struct OwnedCoercions<S, T: ?Sized + 'static> {
+    pub boxed: fn(Box<S>) -> Box<T>,
+    pub pinned_box: fn(Pin<Box<S>>) -> Pin<Box<T>>,
+    pub rc: fn(Rc<S>) -> Rc<T>,
+    #[cfg(target_has_atomic = "ptr")]
+    pub arc: fn(Arc<S>) -> Arc<T>,
+}
+
+/// The coercions of the owning pointers to `S`, which only exist with the `alloc` feature.
+#[cfg(not(feature = "alloc"))]
+#[doc(hidden)]
+pub struct OwnedCoercions<S, T: ?Sized + 'static>(pub PhantomData<S>, pub PhantomData<T>);
+
+/// Says whether a type can be cast to the trait object type `T`, for the conditional entries of
+/// [downcast_trait_impl_for](macro.downcast_trait_impl_for.html). `COERCIONS` is None by default,
+/// and holds the coercions of the type if it implements the trait.
+///
+/// The macro implements it for the concrete types it is given. A generic implementation needs it
+/// for every type it is used with, which can be a blanket implementation when the trait follows
+/// from a bound:
+/// ```
+/// use downcast_trait::prelude::*;
+/// use std::fmt::{self, Display};
+///
+/// struct Cell<T>(T);
+/// impl<T: Display> Display for Cell<T> {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         self.0.fmt(f)
+///     }
+/// }
+/// impl<T: Display + 'static> ConditionalCast<dyn Display> for Cell<T> {
+///     const COERCIONS: Option<Coercions<Self, dyn Display>> = Some(downcast_trait_coercions!());
+/// }
+/// ```
+/// or one implementation for each type, leaving out `COERCIONS` for the types without the trait:
+/// ```
+/// # use downcast_trait::prelude::*;
+/// # use std::fmt::{self, Display};
+/// # struct Cell<T>(T);
+/// # impl<T: Display> Display for Cell<T> {
+/// #     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+/// #         self.0.fmt(f)
+/// #     }
+/// # }
+/// impl ConditionalCast<dyn Display> for Cell<u8> {
+///     const COERCIONS: Option<Coercions<Self, dyn Display>> = Some(downcast_trait_coercions!());
+/// }
+/// impl ConditionalCast<dyn Display> for Cell<Vec<u8>> {}
+/// ```
+pub trait ConditionalCast<T: ?Sized + 'static>: Sized + 'static {
+    /// The coercions to `T`, or None if the type does not implement the trait.
+    const COERCIONS: Option<Coercions<Self, T>> = None;
+}
+
+/// Answers a shared cast to the conditional entry `T`, if `S` implements it. This and the other
+/// `provide_*_if` functions are used by
+/// [downcast_trait_impl_for](../macro.downcast_trait_impl_for.html).
+#[doc(hidden)]
+pub fn provide_ref_if<'s, S: ConditionalCast<T>, T: ?Sized + 'static>(
+    value: &'s S,
+    slot: &mut TraitSlot<'s>,
+) {
+    if let Some(coercions) = S::COERCIONS {
+        slot.provide_ref::<T>((coercions.by_ref)(value));
+        slot.provide_ptr::<T, S>(value, coercions.ptr)
+    }
+}
+
+#[doc(hidden)]
+pub fn provide_mut_if<'s, S: ConditionalCast<T>, T: ?Sized + 'static>(
+    value: &'s mut S,
+    slot: &mut TraitSlot<'s>,
+) {
+    if let Some(coercions) = S::COERCIONS {
+        slot.provide_mut::<T>((coercions.by_mut)(value))
+    }
+}
+
+#[doc(hidden)]
+pub fn provide_pin_if<'s, S: ConditionalCast<T>, T: ?Sized + 'static>(
+    value: Pin<&'s mut S>,
+    slot: &mut TraitSlot<'s>,
+) {
+    if let Some(coercions) = S::COERCIONS {
+        slot.provide_pin::<T>((coercions.pinned)(value))
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub fn provide_box_if<'s, S: ConditionalCast<T> + DowncastTrait, T: ?Sized + 'static>(
+    value: Box<S>,
+    slot: &mut TraitSlot<'s>,
+) -> Result<Provided<'s>, Box<dyn DowncastTrait>> {
+    let Some(coercions) = S::COERCIONS else {
+        return Err(value);
+    };
+    match slot.provide_box::<T, S>(value, coercions.owned.boxed) {
+        Ok(provided) => Ok(provided),
+        Err(value) => Err(value),
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub fn provide_pin_box_if<'s, S: ConditionalCast<T> + DowncastTrait, T: ?Sized + 'static>(
+    value: Pin<Box<S>>,
+    slot: &mut TraitSlot<'s>,
+) -> Result<Provided<'s>, Pin<Box<dyn DowncastTrait>>> {
+    let Some(coercions) = S::COERCIONS else {
+        return Err(value);
+    };
+    match slot.provide_pin_box::<T, S>(value, coercions.owned.pinned_box) {
+        Ok(provided) => Ok(provided),
+        Err(value) => Err(value),
+    }
+}
+
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub fn provide_rc_if<'s, S: ConditionalCast<T> + DowncastTrait, T: ?Sized + 'static>(
+    value: Rc<S>,
+    slot: &mut TraitSlot<'s>,
+) -> Result<Provided<'s>, Rc<dyn DowncastTrait>> {
+    let Some(coercions) = S::COERCIONS else {
+        return Err(value);
+    };
+    match slot.provide_rc::<T, S>(value, coercions.owned.rc) {
+        Ok(provided) => Ok(provided),
+        Err(value) => Err(value),
+    }
+}
+
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+#[doc(hidden)]
+pub fn provide_arc_if<'s, S: ConditionalCast<T> + DowncastTrait, T: ?Sized + 'static>(
+    value: Arc<S>,
+    slot: &mut TraitSlot<'s>,
+) -> Result<Provided<'s>, Arc<dyn DowncastTrait>> {
+    let Some(coercions) = S::COERCIONS else {
+        return Err(value);
+    };
+    match slot.provide_arc::<T, S>(value, coercions.owned.arc) {
+        Ok(provided) => Ok(provided),
+        Err(value) => Err(value),
+    }
+}
+
+/// This macro builds the [Coercions](struct.Coercions.html) of a type to a trait object type it
+/// implements, for a [ConditionalCast](trait.ConditionalCast.html) implementation. Both types are
+/// inferred from where it is used.
+#[macro_export]
+macro_rules! downcast_trait_coercions
+{
+    () => {
+        $crate::Coercions {
+            by_ref: |value| value,
+            by_mut: |value| value,
+            pinned: |value| value,
+            ptr: |value| value,
+            owned: $crate::__downcast_trait_owned_coercions!(),
+        }
+    };
+}
+
+/// This macro is used internally by [downcast_trait_coercions](macro.downcast_trait_coercions.html)
+/// to build the coercions of the owning pointers.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "alloc")]
+macro_rules! __downcast_trait_owned_coercions
+{
+    () => {
+        $crate::__private::OwnedCoercions {
+            boxed: |value| value,
+            pinned_box: |value| value,
+            rc: |value| value,
+            #[cfg(target_has_atomic = "ptr")]
+            arc: |value| value,
+        }
+    };
+}
+
+/// This macro is used internally by [downcast_trait_coercions](macro.downcast_trait_coercions.html)
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "alloc"))]
+macro_rules! __downcast_trait_owned_coercions
+{
+    () => {
+        $crate::__private::OwnedCoercions(
+            $crate::__private::PhantomData,
+            $crate::__private::PhantomData,
+        )
+    };
+}
+
+/// This macro implements [DowncastTrait](trait.DowncastTrait.html) for concrete instantiations of
+/// a generic type. Targets prefixed with `?` are only answered by the instantiations that
+/// implement the trait, and are left out of their
+/// [supported_traits](trait.DowncastTrait.html#method.supported_traits) otherwise.
+/// ```
+/// use downcast_trait::prelude::*;
+/// use std::fmt::{self, Debug, Display};
+///
+/// #[derive(Debug)]
+/// struct Cell<T>(T);
+/// impl<T: Display> Display for Cell<T> {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         self.0.fmt(f)
+///     }
+/// }
+/// downcast_trait_impl_for!(Cell<u8>, Cell<Vec<u8>> => dyn Debug, ?dyn Display + Send);
+///
+/// assert!(downcast_trait!(dyn Display + Send, Cell(1u8).to_downcast_trait()).is_some());
+/// assert!(downcast_trait!(dyn Display + Send, Cell(vec![1u8]).to_downcast_trait()).is_none());
+/// assert!(downcast_trait!(dyn Debug, Cell(vec![1u8]).to_downcast_trait()).is_some());
+/// ```
+///
+/// With `impl<T, ...>` in front of the type, the macro writes a generic implementation for every
+/// `Cell<T>` that implements the unconditional traits and
+/// [ConditionalCast](trait.ConditionalCast.html) for each conditional one. The type parameters
+/// must be `'static`. `ConditionalCast` says whether each instantiation answers, and is written
+/// separately, as its own generic implementation or one for each type:
+/// ```
+/// use downcast_trait::prelude::*;
+/// use std::fmt::{self, Debug, Display};
+///
+/// #[derive(Debug)]
+/// struct Cell<T>(T);
+/// impl<T: Display> Display for Cell<T> {
+///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+///         self.0.fmt(f)
+///     }
+/// }
+/// downcast_trait_impl_for!(impl<T> Cell<T> => dyn Debug, ?dyn Display);
+/// impl ConditionalCast<dyn Display> for Cell<&'static str> {
+///     const COERCIONS: Option<Coercions<Self, dyn Display>> = Some(downcast_trait_coercions!());
+/// }
+/// impl ConditionalCast<dyn Display> for Cell<Vec<u8>> {}
+///
+/// assert!(downcast_trait!(dyn Display, Cell("text").to_downcast_trait()).is_some());
+/// assert!(downcast_trait!(dyn Display, Cell(vec![1u8]).to_downcast_trait()).is_none());
+/// assert!(downcast_trait!(dyn Debug, Cell(vec![1u8]).to_downcast_trait()).is_some());
+/// ```
+#[macro_export]
+macro_rules! downcast_trait_impl_for
+{
+    (impl<$($param:ident),+ $(,)?> $self_type:ty => $($entries:tt)+) => {
+        $crate::__downcast_trait_split_list!(
+            @munch __downcast_trait_impl_for (@generic [$($param),+] $self_type) [] [] []
+            $($entries)+
+        );
+    };
+    ($($self_type:ty),+ $(,)? => $($entries:tt)+) => {
+        $crate::__downcast_trait_split_list!(
+            @munch __downcast_trait_impl_for (@types [$($self_type),+]) [] [] [] $($entries)+
+        );
+    };
+}
+
+/// This macro is used internally by [downcast_trait_impl_for](macro.downcast_trait_impl_for.html)
+/// to sort the entries into unconditional and conditional ones, and implement the traits for each
+/// type.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_impl_for
+{
+    (@sort $types:tt [$($plain:tt)*] [$($maybe:tt)*] {? dyn $($entry:tt)+} $($rest:tt)*) => {
+        $crate::__downcast_trait_impl_for!(@sort $types [$($plain)*] [$($maybe)* {$($entry)+}] $($rest)*);
+    };
+    (@sort $types:tt [$($plain:tt)*] [$($maybe:tt)*] {dyn $($entry:tt)+} $($rest:tt)*) => {
+        $crate::__downcast_trait_impl_for!(@sort $types [$($plain)* {$($entry)+}] [$($maybe)*] $($rest)*);
+    };
+    (@sort (@generic [$($param:ident),+] $self_type:ty) $plain:tt $maybe:tt) => {
+        $crate::__downcast_trait_impl_for!(@impl [$($param),+] $self_type $plain $maybe);
+    };
+    (@sort (@types [$($self_type:ty),+]) $plain:tt $maybe:tt) => {
+        const _: () = {
+            #[allow(dead_code)]
+            struct __DowncastTraitProbe<S: ?Sized, T: ?Sized>(
+                $crate::__private::PhantomData<S>,
+                $crate::__private::PhantomData<T>,
+            );
+            #[allow(dead_code)]
+            trait __DowncastTraitFallback<S, T: ?Sized + 'static> {
+                const COERCIONS: $crate::__private::Option<$crate::Coercions<S, T>> =
+                    $crate::__private::None;
+            }
+            impl<S, T: ?Sized + 'static> __DowncastTraitFallback<S, T> for __DowncastTraitProbe<S, T> {}
+            $crate::__downcast_trait_impl_for!(@probes $maybe);
+            $(
+            $crate::__downcast_trait_impl_for!(@probe $self_type $maybe);
+            $crate::__downcast_trait_impl_for!(@impl [] $self_type $plain $maybe);
+            )+
+        };
+    };
+    (@probes [$({$($maybe:tt)+})*]) => {
+        $(
+        impl<S: $($maybe)+ + 'static> __DowncastTraitProbe<S, dyn $($maybe)+> {
+            #[allow(dead_code)]
+            const COERCIONS: $crate::__private::Option<$crate::Coercions<S, dyn $($maybe)+>> =
+                $crate::__private::Some($crate::downcast_trait_coercions!());
+        }
+        )*
+    };
+    (@probe $self_type:ty [$({$($maybe:tt)+})*]) => {
+        $(
+        impl $crate::ConditionalCast<dyn $($maybe)+> for $self_type {
+            const COERCIONS: $crate::__private::Option<$crate::Coercions<Self, dyn $($maybe)+>> =
+                __DowncastTraitProbe::<Self, dyn $($maybe)+>::COERCIONS;
+        }
+        )*
+    };
+    (@impl [$($param:ident),*] $self_type:ty [$({$($plain:tt)+})*] [$({$($maybe:tt)+})*]) => {
+        impl<$($param: 'static),*> $crate::DowncastTrait for $self_type
+        where
+            $($self_type: $($plain)+,)*
+            $($self_type: $crate::ConditionalCast<dyn $($maybe)+>,)*
+        {
+            $crate::__downcast_trait_impl_convert_to_ref!($(dyn $($plain)+),* ; $(dyn $($maybe)+),* ; ; []);
+            $crate::__downcast_trait_impl_convert_to_mut!($(dyn $($plain)+),* ; $(dyn $($maybe)+),* ; ; []);
+            $crate::__downcast_trait_impl_convert_to_box!($(dyn $($plain)+),* ; $(dyn $($maybe)+),* ; ; []);
+            $crate::__downcast_trait_impl_convert_to_rc!($(dyn $($plain)+),* ; $(dyn $($maybe)+),* ; ; []);
+            $crate::__downcast_trait_capability_mask!([$(dyn $($plain)+),*] [] []);
+            fn listed_traits() -> &'static [$crate::TraitDescriptor] {
+                let (all, count) = const {
+                    &$crate::__private::select_supported([
+                        $(($crate::TraitDescriptor::new::<dyn $($plain)+>(true, true, true), true),)*
+                        $((
+                            $crate::TraitDescriptor::new::<dyn $($maybe)+>(true, true, true),
+                            <Self as $crate::ConditionalCast<dyn $($maybe)+>>::COERCIONS.is_some(),
+                        ),)*
+                    ])
+                };
+                &all[..*count]
+            }
+        }
+    };
+    (($($types:tt)*) $($entries:tt)*) => {
+        $crate::__downcast_trait_impl_for!(@sort ($($types)*) [] [] $($entries)*);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::{Coercions, ConditionalCast};
+    use crate::{downcast_trait, DowncastTrait};
+    use core::fmt::{self, Debug, Display};
+    #[cfg(feature = "alloc")]
+    use std::boxed::Box;
+    use std::{string::ToString, vec, vec::Vec};
+
+    #[derive(Debug)]
+    struct Cell<T>(T);
+    impl<T: Display> Display for Cell<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "[{}]", self.0)
+        }
+    }
+    downcast_trait_impl_for!(Cell<u8>, Cell<Vec<u8>> => dyn Debug, ?dyn Display);
+
+    #[test]
+    fn conditional_entries() {
+        let displayable = Cell(7u8);
+        let shown = downcast_trait!(dyn Display, displayable.to_downcast_trait()).unwrap();
+        assert_eq!(shown.to_string(), "[7]");
+        assert!(downcast_trait!(dyn Debug, displayable.to_downcast_trait()).is_some());
+        assert_eq!(displayable.supported_traits().len(), 2);
+
+        let mut hidden = Cell(vec![7u8]);
+        assert!(downcast_trait!(dyn Display, hidden.to_downcast_trait()).is_none());
+        assert!(crate::downcast_trait_mut!(dyn Display, hidden.to_downcast_trait_mut()).is_none());
+        let pinned = core::pin::Pin::new(&mut hidden);
+        assert!(crate::downcast_trait_pin!(dyn Display, pinned).is_none());
+        assert!(downcast_trait!(dyn Debug, hidden.to_downcast_trait()).is_some());
+        let traits = hidden.supported_traits();
+        assert_eq!(traits.len(), 1);
+        assert_eq!(traits[0].type_id(), core::any::TypeId::of::<dyn Debug>());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn conditional_owned_entries() {
+        let shown = crate::downcast_trait_box!(dyn Display, Box::new(Cell(7u8))).unwrap();
+        assert_eq!(shown.to_string(), "[7]");
+        let refused = crate::downcast_trait_box!(dyn Display, Box::new(Cell(vec![7u8])));
+        match refused {
+            Ok(_) => panic!("Cell<Vec<u8>> does not implement Display"),
+            Err(original) => assert!(original.is::<Cell<Vec<u8>>>()),
+        }
+        let pinned = crate::downcast_trait_pin_box!(dyn Display, Box::pin(Cell(7u8))).unwrap();
+        assert_eq!(pinned.to_string(), "[7]");
+        let shared = std::rc::Rc::new(Cell(vec![7u8]));
+        assert!(crate::downcast_trait_rc!(dyn Display, shared).is_err());
+    }
+
+    #[derive(Debug)]
+    struct Tagged<T>(T);
+    impl<T: Display> Display for Tagged<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "<{}>", self.0)
+        }
+    }
+    downcast_trait_impl_for!(impl<T> Tagged<T> => dyn Debug, ?dyn Display + Send);
+    impl ConditionalCast<dyn Display + Send> for Tagged<u8> {
+        const COERCIONS: Option<Coercions<Self, dyn Display + Send>> =
+            Some(crate::downcast_trait_coercions!());
+    }
+    impl ConditionalCast<dyn Display + Send> for Tagged<Vec<u8>> {}
+
+    #[test]
+    fn generic_conditional_entries() {
+        let mut displayable = Tagged(7u8);
+        let shown = downcast_trait!(dyn Display + Send, displayable.to_downcast_trait()).unwrap();
+        assert_eq!(shown.to_string(), "<7>");
+        let object = displayable.to_downcast_trait_mut();
+        assert!(crate::downcast_trait_mut!(dyn Display + Send, object).is_some());
+        assert!(downcast_trait!(dyn Display, displayable.to_downcast_trait()).is_none());
+        assert_eq!(displayable.supported_traits().len(), 2);
+
+        let hidden = Tagged(vec![7u8]);
+        assert!(downcast_trait!(dyn Display + Send, hidden.to_downcast_trait()).is_none());
+        assert!(downcast_trait!(dyn Debug, hidden.to_downcast_trait()).is_some());
+        let traits = hidden.supported_traits();
+        assert_eq!(traits.len(), 1);
+        assert_eq!(traits[0].type_id(), core::any::TypeId::of::<dyn Debug>());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn generic_conditional_owned_entries() {
+        let shown = crate::downcast_trait_box!(dyn Display + Send, Box::new(Tagged(7u8))).unwrap();
+        assert_eq!(shown.to_string(), "<7>");
+        let refused = crate::downcast_trait_box!(dyn Display + Send, Box::new(Tagged(vec![7u8])));
+        assert!(refused.err().unwrap().is::<Tagged<Vec<u8>>>());
+        let shared = std::rc::Rc::new(Tagged(7u8));
+        let shown = crate::downcast_trait_rc!(dyn Display + Send, shared).ok().unwrap();
+        assert_eq!(shown.to_string(), "<7>");
+    }
+}
@@ -0,0 +1,329 @@
+//! Forwarding of unmatched casts to an inner object, for decorators and newtypes.
+//!
+//! An implementation written as `downcast_trait_impl_convert_to!(delegate = self.inner, ...)`
+//! first answers the traits it lists itself, and passes every other cast on to the
+//! [DowncastTrait](../trait.DowncastTrait.html) implementation of `self.inner`. The inner object
+//! can be a concrete type, or a box holding a trait object whose trait has `DowncastTrait` as a
+//! supertrait.
+//!
+//! A boxed cast that is answered by the inner object can not return the wrapper, so the wrapper is
+//! taken apart and only the inner object is kept. The rest of the wrapper is dropped. Before giving
+//! up the wrapper the inner object is asked whether it would answer the boxed cast, and if it would
+//! not the original box is handed back unchanged. An inner object that answers but then refuses the
+//! cast is put back, and the wrapper is handed back as well. `Rc` and `Arc` casts can not move the
+//! inner object out of the shared wrapper, and are only answered for the listed traits. Pinned
+//! casts are only answered for the listed traits too, as the inner object is not known to be
+//! structurally pinned.
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use core::any::TypeId;
+
+#[cfg(feature = "alloc")]
+use crate::{DowncastTrait, Provided, TraitSlot};
+
+/// The inner object of a delegating implementation after a refused boxed cast.
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub enum Refused<F> {
+    /// The inner object, which is put back into the wrapper.
+    Field(F),
+    /// The object that the implementation of the inner object handed back instead of itself.
+    Other(Box<dyn DowncastTrait>),
+}
+
+/// Answers a boxed cast with the inner object of a delegating implementation, when it is stored
+/// by value.
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub trait IntoDowncastBox: Sized {
+    fn delegate_box<'s>(self, slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Refused<Self>>;
+}
+
+#[cfg(feature = "alloc")]
+impl<T: DowncastTrait + 'static> IntoDowncastBox for T {
+    fn delegate_box<'s>(self, slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Refused<Self>> {
+        let boxed: Box<dyn DowncastTrait> = Box::new(self);
+        match boxed.convert_to_trait_box(slot) {
+            Ok(provided) => Ok(provided),
+            Err(boxed) if (*boxed).concrete_type_id() == TypeId::of::<T>() => {
+                // Safety: the concrete type comes from the sealed supertrait and can not be
+                // misreported, so the box holds a `T`.
+                let inner = unsafe { Box::from_raw(Box::into_raw(boxed) as *mut T) };
+                Err(Refused::Field(*inner))
+            }
+            Err(boxed) => Err(Refused::Other(boxed)),
+        }
+    }
+}
+
+/// Answers a boxed cast with the inner object of a delegating implementation, when it is already
+/// boxed.
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub trait BoxIntoDowncastBox: Sized {
+    fn delegate_box<'s>(self, slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Refused<Self>>;
+}
+
+#[cfg(feature = "alloc")]
+impl<T: ?Sized + DowncastTrait> BoxIntoDowncastBox for Box<T> {
+    fn delegate_box<'s>(self, slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Refused<Self>> {
+        let owner = (*self).concrete_type_id();
+        let raw = Box::into_raw(self);
+        // Safety: `raw` was just taken from a box.
+        let boxed = unsafe { Box::from_raw(raw) }.to_downcast_trait_box();
+        match boxed.convert_to_trait_box(slot) {
+            Ok(provided) => Ok(provided),
+            // The same object at the same address is the box that was passed in, and is boxed as
+            // `T` again.
+            Err(boxed) if (*boxed).concrete_type_id() == owner => {
+                let handed_back = Box::into_raw(boxed);
+                if handed_back as *mut () == raw as *mut () {
+                    // Safety: the allocation is the one `raw` was taken from, and `handed_back`
+                    // no longer owns it.
+                    Err(Refused::Field(unsafe { Box::from_raw(raw) }))
+                } else {
+                    // Safety: `handed_back` was just taken from a box.
+                    Err(Refused::Other(unsafe { Box::from_raw(handed_back) }))
+                }
+            }
+            Err(boxed) => Err(Refused::Other(boxed)),
+        }
+    }
+}
+
+/// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+/// to answer a cast that is not in the list, by forwarding it to the delegate field if one is
+/// given.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_delegate
+{
+    (ref $this:ident, $slot:ident, []) => {
+        ()
+    };
+    (ref $this:ident, $slot:ident, [$($field:tt)+]) => {{
+        #[allow(unused_imports)]
+        use $crate::DowncastTrait as _;
+        $this.$($field)+.to_downcast_trait().convert_to_trait($slot)
+    }};
+    (mut $this:ident, $slot:ident, []) => {
+        ()
+    };
+    (mut $this:ident, $slot:ident, [$($field:tt)+]) => {{
+        #[allow(unused_imports)]
+        use $crate::DowncastTrait as _;
+        $this.$($field)+.to_downcast_trait_mut().convert_to_trait_mut($slot)
+    }};
+    (box $this:ident, $slot:ident, []) => {
+        $crate::__private::Err($this)
+    };
+    (box $this:ident, $slot:ident, [$($field:tt)+]) => {{
+        #[allow(unused_imports)]
+        use $crate::DowncastTrait as _;
+        if $crate::__private::answers_owned($this.$($field)+.to_downcast_trait(), $slot.target()) {
+            #[allow(unused_imports)]
+            use $crate::__private::{BoxIntoDowncastBox as _, IntoDowncastBox as _};
+            let mut this = $this;
+            let inner = (*this).$($field)+;
+            match inner.delegate_box($slot) {
+                $crate::__private::Ok(provided) => $crate::__private::Ok(provided),
+                // The inner object answered the probe but refused the cast, so it is put back.
+                $crate::__private::Err($crate::__private::Refused::Field(inner)) => {
+                    (*this).$($field)+ = inner;
+                    $crate::__private::Err(this)
+                }
+                $crate::__private::Err($crate::__private::Refused::Other(other)) => {
+                    $crate::__private::Err(other)
+                }
+            }
+        } else {
+            $crate::__private::Err($this)
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use crate::{
+        downcast_trait, downcast_trait_impl_convert_to, downcast_trait_mut, DowncastTrait,
+    };
+    use std::{
+        boxed::Box,
+        format,
+        string::{String, ToString},
+    };
+
+    trait Widget: DowncastTrait {
+        fn name(&self) -> String;
+    }
+    trait Clickable {
+        fn click(&mut self) -> u32;
+    }
+    trait Framed {
+        fn width(&self) -> u32;
+    }
+    trait Described {
+        fn text(&self) -> &str;
+    }
+
+    struct Button {
+        clicks: u32,
+    }
+    impl Widget for Button {
+        fn name(&self) -> String {
+            "button".to_string()
+        }
+    }
+    impl Clickable for Button {
+        fn click(&mut self) -> u32 {
+            self.clicks += 1;
+            self.clicks
+        }
+    }
+    impl DowncastTrait for Button {
+        downcast_trait_impl_convert_to!(dyn Widget, dyn Clickable);
+    }
+
+    struct Bordered<W> {
+        inner: W,
+        width: u32,
+    }
+    impl<W> Framed for Bordered<W> {
+        fn width(&self) -> u32 {
+            self.width
+        }
+    }
+    impl<W: Widget + Send + Sync + 'static> Widget for Bordered<W> {
+        fn name(&self) -> String {
+            format!("bordered {}", self.inner.name())
+        }
+    }
+    impl<W: DowncastTrait + Send + Sync + 'static> DowncastTrait for Bordered<W> {
+        downcast_trait_impl_convert_to!(delegate = self.inner, dyn Framed + Send + Sync);
+    }
+
+    struct Tooltip {
+        inner: Box<dyn Widget>,
+        text: String,
+    }
+    impl Described for Tooltip {
+        fn text(&self) -> &str {
+            &self.text
+        }
+    }
+    impl DowncastTrait for Tooltip {
+        downcast_trait_impl_convert_to!(delegate = self.inner, dyn Described);
+    }
+
+    #[cfg(feature = "alloc")]
+    struct Caption(String);
+    #[cfg(feature = "alloc")]
+    impl Described for Caption {
+        fn text(&self) -> &str {
+            &self.0
+        }
+    }
+
+    /// Answers shared casts to `Described` with a field, which boxed casts can not hand over.
+    #[cfg(feature = "alloc")]
+    struct Captioned {
+        caption: Caption,
+    }
+    #[cfg(feature = "alloc")]
+    impl DowncastTrait for Captioned {
+        downcast_trait_impl_convert_to!(dyn Described => self.caption);
+    }
+
+    struct Transparent(Button);
+    impl DowncastTrait for Transparent {
+        downcast_trait_impl_convert_to!(delegate = self.0);
+    }
+
+    #[test]
+    fn delegated_casts() {
+        let mut bordered = Bordered {
+            inner: Button { clicks: 0 },
+            width: 2,
+        };
+        let object = bordered.to_downcast_trait_mut();
+        assert_eq!(downcast_trait!(dyn Framed, object).unwrap().width(), 2);
+        assert!(downcast_trait!(dyn Framed + Send + Sync, object).is_some());
+        assert_eq!(
+            downcast_trait!(dyn Widget, object).unwrap().name(),
+            "button"
+        );
+        assert!(object.downcast_ref::<Bordered<Button>>().is_some());
+        assert_eq!(
+            downcast_trait_mut!(dyn Clickable, object).unwrap().click(),
+            1
+        );
+        assert!(downcast_trait!(dyn Described, object).is_none());
+        assert_eq!(object.supported_traits().len(), 1);
+
+        let mut tooltip = Tooltip {
+            inner: Box::new(bordered),
+            text: "help".to_string(),
+        };
+        let object = tooltip.to_downcast_trait_mut();
+        assert_eq!(
+            downcast_trait!(dyn Described, object).unwrap().text(),
+            "help"
+        );
+        assert_eq!(downcast_trait!(dyn Framed, object).unwrap().width(), 2);
+        assert_eq!(
+            downcast_trait_mut!(dyn Clickable, object).unwrap().click(),
+            2
+        );
+        assert!(object.downcast_ref::<Bordered<Button>>().is_some());
+        assert!(object.downcast_ref::<Button>().is_some());
+
+        let transparent = Transparent(Button { clicks: 0 });
+        assert!(downcast_trait!(dyn Widget, transparent.to_downcast_trait()).is_some());
+        assert!(transparent.supported_traits().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn delegated_box_casts() {
+        let tooltip: Box<dyn DowncastTrait> = Box::new(Tooltip {
+            inner: Box::new(Button { clicks: 3 }),
+            text: "help".to_string(),
+        });
+        // The wrapper is kept when neither it nor the inner object supports the cast.
+        let tooltip = match crate::downcast_trait_box!(dyn Framed, tooltip) {
+            Ok(_) => panic!("Button is not Framed"),
+            Err(original) => original,
+        };
+        assert!(tooltip.is::<Tooltip>());
+        // Casts answered by the inner object drop the wrapper.
+        let mut clickable = crate::downcast_trait_box!(dyn Clickable, tooltip)
+            .ok()
+            .unwrap();
+        assert_eq!(clickable.click(), 4);
+
+        let bordered: Box<dyn DowncastTrait> = Box::new(Bordered {
+            inner: Button { clicks: 0 },
+            width: 1,
+        });
+        let bordered = crate::downcast_trait_box!(dyn Framed, bordered)
+            .ok()
+            .unwrap();
+        assert_eq!(bordered.width(), 1);
+
+        // The inner object answers shared casts to Described but not boxed ones, so the wrapper
+        // is kept.
+        let bordered: Box<dyn DowncastTrait> = Box::new(Bordered {
+            inner: Captioned {
+                caption: Caption("tip".to_string()),
+            },
+            width: 1,
+        });
+        assert!(downcast_trait!(dyn Described, &*bordered).is_some());
+        let bordered = crate::downcast_trait_box!(dyn Described, bordered)
+            .err()
+            .unwrap();
+        assert!(bordered.is::<Bordered<Captioned>>());
+    }
+}
@@ -0,0 +1,123 @@
+//! Descriptions of the traits a [DowncastTrait](../trait.DowncastTrait.html) object can be cast to.
+use core::{any::TypeId, fmt};
+
+use crate::DowncastTrait;
+
+/// Describes one trait that an object can be cast to, and which kinds of casts are available
+/// for it. The descriptors of an object are returned by
+/// [supported_traits](../trait.DowncastTrait.html#method.supported_traits).
+#[derive(Clone, Copy)]
+pub struct TraitDescriptor {
+    type_id: TypeId,
+    type_name: fn() -> &'static str,
+    ref_cast: bool,
+    mut_cast: bool,
+    owned_cast: bool,
+    /// The `TypeId` of the trait without the bounds of the entry, which is also answered.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) unbounded: TypeId,
+    /// True if the entry is answered by a field rather than the object itself.
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pub(crate) projected: bool,
+}
+
+impl TraitDescriptor {
+    /// Creates a descriptor for the trait object type `T`. This is used by
+    /// [downcast_trait_impl_convert_to](../macro.downcast_trait_impl_convert_to.html).
+    #[doc(hidden)]
+    pub const fn new<T: ?Sized + 'static>(
+        ref_cast: bool,
+        mut_cast: bool,
+        owned_cast: bool,
+    ) -> Self {
+        TraitDescriptor {
+            type_id: TypeId::of::<T>(),
+            type_name: core::any::type_name::<T>,
+            ref_cast,
+            mut_cast,
+            owned_cast,
+            unbounded: TypeId::of::<T>(),
+            projected: false,
+        }
+    }
+
+    /// Creates a descriptor for the entry `T` of a cast list, such as `dyn Container + Send`, whose
+    /// trait without bounds is `U`. `projected` is true for entries answered by a field, which
+    /// can not be cast on the owned paths. This is used by
+    /// [downcast_trait_impl_convert_to](../macro.downcast_trait_impl_convert_to.html).
+    #[doc(hidden)]
+    pub const fn entry<T: ?Sized + 'static, U: ?Sized + 'static>(projected: bool) -> Self {
+        TraitDescriptor {
+            unbounded: TypeId::of::<U>(),
+            projected,
+            ..TraitDescriptor::new::<T>(true, true, !projected)
+        }
+    }
+
+    /// The `TypeId` of the trait object type, e.g. `TypeId::of::<dyn Container>()`.
+    pub fn type_id(&self) -> TypeId {
+        self.type_id
+    }
+
+    /// The name of the trait object type, e.g. `dyn my_crate::Container`.
+    pub fn type_name(&self) -> &'static str {
+        (self.type_name)()
+    }
+
+    /// Returns true if the object can be cast to a shared reference of the trait.
+    pub fn supports_ref(&self) -> bool {
+        self.ref_cast
+    }
+
+    /// Returns true if the object can be cast to a mutable reference of the trait.
+    pub fn supports_mut(&self) -> bool {
+        self.mut_cast
+    }
+
+    /// Returns true if a `Box`, `Rc` or `Arc` holding the object can be cast to the trait. This is
+    /// always false without the `alloc` feature.
+    pub fn supports_box(&self) -> bool {
+        self.owned_cast && cfg!(feature = "alloc")
+    }
+}
+
+/// Moves the descriptors in `all` whose flag is set to the front, and returns them with their
+/// count. This is used by [downcast_trait_impl_for](../macro.downcast_trait_impl_for.html) to
+/// list the supported traits of a type, whose length can not be named in a generic
+/// implementation.
+#[doc(hidden)]
+pub const fn select_supported<const N: usize>(
+    all: [(TraitDescriptor, bool); N],
+) -> ([TraitDescriptor; N], usize) {
+    let mut selected = [TraitDescriptor::new::<()>(false, false, false); N];
+    let mut count = 0;
+    let mut index = 0;
+    while index < N {
+        if all[index].1 {
+            selected[count] = all[index].0;
+            count += 1;
+        }
+        index += 1;
+    }
+    (selected, count)
+}
+
+impl fmt::Debug for TraitDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TraitDescriptor")
+            .field("type_name", &self.type_name())
+            .field("ref", &self.supports_ref())
+            .field("mut", &self.supports_mut())
+            .field("box", &self.supports_box())
+            .finish()
+    }
+}
+
+impl fmt::Debug for dyn DowncastTrait + '_ {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DowncastTrait")
+            .field("type_name", &self.type_name())
+            .field("traits", &self.supported_traits())
+            .finish()
+    }
+}
@@ -0,0 +1,444 @@
+//! Capability bitsets over a declared family of traits.
+//!
+//! A family assigns each of its traits a bit, so the traits an object supports can be summarized
+//! in one integer and tested with a single mask operation. The mask of an object is computed by
+//! its implementation from the traits listed for its type, without asking for any cast, and with
+//! `std` it is computed only once for each type and family. An object delegating to a field adds
+//! the mask of the field to its own, and a [Composite](../struct.Composite.html) looks its parts
+//! up directly. Hand-written implementations are probed with a shared cast to each trait of the
+//! family instead.
+use core::{
+    any::TypeId,
+    fmt,
+    marker::PhantomData,
+    ops::{BitAnd, BitOr},
+};
+#[cfg(feature = "std")]
+use core::{
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+use crate::{DowncastTrait, TraitDescriptor};
+
+/// A closed family of traits, declared with
+/// [downcast_trait_family](../macro.downcast_trait_family.html). The traits are numbered by
+/// their position in [MEMBERS](#associatedconstant.MEMBERS).
+pub trait CapabilityFamily: 'static {
+    /// The traits of the family, in bit order. There can be at most 64.
+    const MEMBERS: &'static [TraitDescriptor];
+}
+
+/// The set of traits of the family `F` that an object can be cast to.
+pub struct Capabilities<F> {
+    bits: u64,
+    family: PhantomData<F>,
+}
+
+impl<F: CapabilityFamily> Capabilities<F> {
+    /// The empty set.
+    pub const fn empty() -> Self {
+        Self::from_bits(0)
+    }
+
+    /// Creates a set from its bits, where bit `n` is the trait `F::MEMBERS[n]`.
+    pub const fn from_bits(bits: u64) -> Self {
+        Capabilities {
+            bits,
+            family: PhantomData,
+        }
+    }
+
+    /// The set containing only the trait object type `T`. It is empty if `T` is not in the family.
+    pub fn of<T: ?Sized + 'static>() -> Self {
+        F::MEMBERS
+            .iter()
+            .position(|member| member.type_id() == TypeId::of::<T>())
+            .map_or(Self::empty(), |index| Self::from_bits(1 << index))
+    }
+
+    /// Probes `src` with a shared cast to every trait of the family.
+    pub fn probe(src: &dyn DowncastTrait) -> Self {
+        Self::from_bits(mask_of(F::MEMBERS, |id| crate::slot::answers(src, id)))
+    }
+
+    /// Returns the traits of the family that `src` can be cast to. The mask is computed from the
+    /// traits listed for the type of `src`, and `src` is probed if its implementation does not
+    /// provide a mask.
+    pub fn of_object(src: &dyn DowncastTrait) -> Self {
+        Self::from_bits(object_mask(src, TypeId::of::<F>(), F::MEMBERS))
+    }
+
+    /// The bits of the set, where bit `n` is the trait `F::MEMBERS[n]`.
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    /// Returns true if the set contains no traits.
+    pub fn is_empty(&self) -> bool {
+        self.bits == 0
+    }
+
+    /// Returns true if the set contains the trait object type `T`.
+    pub fn has<T: ?Sized + 'static>(&self) -> bool {
+        !Self::of::<T>().is_empty() && self.has_all(Self::of::<T>())
+    }
+
+    /// Returns true if the set contains every trait in `other`.
+    pub fn has_all(&self, other: Self) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// Returns true if the set contains at least one trait in `other`.
+    pub fn has_any(&self, other: Self) -> bool {
+        self.bits & other.bits != 0
+    }
+
+    /// Iterates over the descriptors of the traits in the set, in bit order.
+    pub fn iter(&self) -> impl Iterator<Item = TraitDescriptor> + '_ {
+        F::MEMBERS
+            .iter()
+            .enumerate()
+            .filter(move |(index, _)| self.bits & 1 << index != 0)
+            .map(|(_, member)| *member)
+    }
+}
+
+impl<F> Clone for Capabilities<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for Capabilities<F> {}
+
+impl<F> PartialEq for Capabilities<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.bits == other.bits
+    }
+}
+
+impl<F> Eq for Capabilities<F> {}
+
+impl<F> BitOr for Capabilities<F> {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Capabilities {
+            bits: self.bits | other.bits,
+            family: PhantomData,
+        }
+    }
+}
+
+impl<F> BitAnd for Capabilities<F> {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Capabilities {
+            bits: self.bits & other.bits,
+            family: PhantomData,
+        }
+    }
+}
+
+impl<F: CapabilityFamily> fmt::Debug for Capabilities<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set()
+            .entries(self.iter().map(|member| member.type_name()))
+            .finish()
+    }
+}
+
+/// Returns the bits of the traits in `members` for which `answers` returns true. This is used by
+/// [downcast_trait_impl_convert_to](../macro.downcast_trait_impl_convert_to.html) to compute the
+/// capability mask of a type.
+#[doc(hidden)]
+pub fn mask_of(members: &[TraitDescriptor], answers: impl Fn(TypeId) -> bool) -> u64 {
+    members
+        .iter()
+        .enumerate()
+        .filter(|(_, member)| answers(member.type_id()))
+        .fold(0, |bits, (index, _)| bits | 1 << index)
+}
+
+/// Returns the bits of the traits in `members`, the traits of the family with the `TypeId`
+/// `family`, that `src` can be cast to. `src` is probed if its implementation does not provide a
+/// mask.
+#[doc(hidden)]
+pub fn object_mask(src: &dyn DowncastTrait, family: TypeId, members: &[TraitDescriptor]) -> u64 {
+    match src.capability_mask(family, members) {
+        Some(bits) => bits,
+        None => mask_of(members, |id| crate::slot::answers(src, id)),
+    }
+}
+
+/// The mask of one type in one family, linked to the masks computed before it.
+#[cfg(feature = "std")]
+struct Mask {
+    owner: TypeId,
+    family: TypeId,
+    bits: u64,
+    next: *const Mask,
+}
+
+/// Lazily computed capability masks, one for each type and family.
+///
+/// The masks live in a `static` inside the generated `capability_mask` function, which is shared
+/// by all instantiations of a generic implementation, so they are kept for each type like the
+/// tables of a `LookupTable`. Without `std` the mask is computed on every call.
+#[doc(hidden)]
+pub struct MaskCache {
+    #[cfg(feature = "std")]
+    masks: AtomicPtr<Mask>,
+}
+
+impl MaskCache {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        MaskCache {
+            #[cfg(feature = "std")]
+            masks: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Returns the mask of the type `owner` in `family`, calling `compute` if this is the first
+    /// time it is asked for.
+    #[cfg(feature = "std")]
+    pub fn get(&self, owner: TypeId, family: TypeId, compute: impl FnOnce() -> u64) -> u64 {
+        let mut next = self.masks.load(Ordering::Acquire);
+        // Safety: the masks are leaked, and are only linked in once they are complete.
+        while let Some(mask) = unsafe { next.as_ref() } {
+            if mask.owner == owner && mask.family == family {
+                return mask.bits;
+            }
+            next = mask.next.cast_mut();
+        }
+        let mask = Box::leak(Box::new(Mask {
+            owner,
+            family,
+            bits: compute(),
+            next: ptr::null(),
+        }));
+        // Another thread may link in a mask for the same type first. Both are equal, and the one
+        // found first is used afterwards.
+        let mut head = self.masks.load(Ordering::Acquire);
+        loop {
+            mask.next = head;
+            match self
+                .masks
+                .compare_exchange_weak(head, mask, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return mask.bits,
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Returns the mask of the type `owner` in `family`, calling `compute` if this is the first
+    /// time it is asked for.
+    #[cfg(not(feature = "std"))]
+    pub fn get(&self, _owner: TypeId, _family: TypeId, compute: impl FnOnce() -> u64) -> u64 {
+        compute()
+    }
+}
+
+// Safety: the masks are immutable once linked in, and only hold plain data.
+#[cfg(feature = "std")]
+unsafe impl Sync for Mask {}
+
+/// This macro is used internally by
+/// [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html) to compute the
+/// capability mask of a type from its answered and projected traits, the supertraits of the
+/// answered ones, and the conditional ones in its listed traits. The mask is computed once for
+/// each type and family. Types that delegate casts to a field add the mask of the field, which
+/// depends on the object and is not kept.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_capability_mask {
+    ([$($target:ty),*] [$($projected:ty),*] $delegate:tt) => {
+        fn capability_mask(
+            &self,
+            family: $crate::__private::TypeId,
+            members: &[$crate::TraitDescriptor],
+        ) -> $crate::__private::Option<u64> {
+            #[allow(unused_imports)]
+            use $crate::__private::NoSupertraits as _;
+            static MASKS: $crate::__private::MaskCache = $crate::__private::MaskCache::new();
+            let listed = <Self as $crate::DowncastTrait>::listed_traits();
+            let bits = MASKS.get($crate::__private::TypeId::of::<Self>(), family, || {
+                $crate::__private::mask_of(members, |id| {
+                    listed.iter().any(|descriptor| descriptor.type_id() == id)
+                        $(|| id == $crate::__private::TypeId::of::<$target>()
+                            || $crate::__private::SupertraitProbe::<$target, Self>::answers(id))*
+                        $(|| id == $crate::__private::TypeId::of::<$projected>())*
+                })
+            });
+            $crate::__private::Some(bits | $crate::__downcast_trait_capability_mask!(
+                @delegate self, family, members, $delegate
+            ))
+        }
+    };
+    (@delegate $this:ident, $family:ident, $members:ident, []) => {
+        0
+    };
+    (@delegate $this:ident, $family:ident, $members:ident, [$($field:tt)+]) => {{
+        #[allow(unused_imports)]
+        use $crate::DowncastTrait as _;
+        $crate::__private::object_mask($this.$($field)+.to_downcast_trait(), $family, $members)
+    }};
+}
+
+/// This macro declares a family of at most 64 traits, whose members can be tested together with
+/// [Capabilities](struct.Capabilities.html):
+/// ```
+/// use downcast_trait::prelude::*;
+/// trait Measure {}
+/// trait Arrange {}
+/// trait Paint {}
+/// downcast_trait_family!(pub Layout: dyn Measure, dyn Arrange, dyn Paint);
+///
+/// struct Label;
+/// impl Measure for Label {}
+/// impl Paint for Label {}
+/// impl DowncastTrait for Label {
+///     downcast_trait_impl_convert_to!(dyn Measure, dyn Paint);
+/// }
+///
+/// let capabilities = Label.to_downcast_trait().capabilities::<Layout>();
+/// let drawable = Capabilities::of::<dyn Measure>() | Capabilities::of::<dyn Paint>();
+/// assert!(capabilities.has_all(drawable));
+/// assert!(!capabilities.has::<dyn Arrange>());
+/// ```
+#[macro_export]
+macro_rules! downcast_trait_family
+{
+    ($vis:vis $name:ident : $(dyn $member:path),+ $(,)?) => {
+        $vis struct $name;
+        impl $crate::CapabilityFamily for $name {
+            const MEMBERS: &'static [$crate::TraitDescriptor] =
+                &[$($crate::TraitDescriptor::new::<dyn $member>(true, true, true)),+];
+        }
+        const _: () = assert!(
+            <$name as $crate::CapabilityFamily>::MEMBERS.len() <= 64,
+            "a trait family can have at most 64 members"
+        );
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::downcast_trait_impl_convert_to;
+    #[cfg(feature = "alloc")]
+    use crate::Composite;
+    #[cfg(feature = "alloc")]
+    use std::boxed::Box;
+    use std::{format, vec::Vec};
+
+    trait Measure {}
+    trait Arrange {}
+    trait Paint {}
+    trait Other {}
+    downcast_trait_family!(Layout: dyn Measure, dyn Arrange, dyn Paint);
+
+    struct Label;
+    impl Measure for Label {}
+    impl Paint for Label {}
+    impl DowncastTrait for Label {
+        downcast_trait_impl_convert_to!(dyn Measure, dyn Paint + Send);
+    }
+
+    struct Panel;
+    impl Measure for Panel {}
+    impl Arrange for Panel {}
+    impl Other for Panel {}
+    impl DowncastTrait for Panel {
+        downcast_trait_impl_convert_to!(dyn Measure, dyn Arrange, dyn Other);
+    }
+
+    #[test]
+    fn capability_masks() {
+        let label = Label.to_downcast_trait().capabilities::<Layout>();
+        assert_eq!(label.bits(), 0b101);
+        assert_eq!(label, Label.to_downcast_trait().capabilities::<Layout>());
+        assert!(label.has::<dyn Paint>());
+        assert!(!label.has::<dyn Other>());
+
+        let panel = Capabilities::<Layout>::probe(Panel.to_downcast_trait());
+        assert_eq!(panel.bits(), 0b011);
+        let arrange = Capabilities::<Layout>::of::<dyn Arrange>();
+        let paint = Capabilities::<Layout>::of::<dyn Paint>();
+        assert!(panel.has_any(arrange | paint));
+        assert!(!panel.has_all(arrange | paint));
+        assert!(!label.has_any(arrange));
+        assert_eq!((label & panel).bits(), 0b001);
+        assert!(Capabilities::<Layout>::of::<dyn Other>().is_empty());
+
+        let names: Vec<_> = label.iter().map(|member| member.type_name()).collect();
+        assert_eq!(names.len(), 2);
+        assert!(names[1].ends_with("Paint"));
+        assert_eq!(format!("{:?}", Capabilities::<Layout>::empty()), "{}");
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn mask_per_type_and_family() {
+        use core::cell::Cell;
+        static MASKS: MaskCache = MaskCache::new();
+        let computed = Cell::new(0);
+        let compute = |bits| {
+            computed.set(computed.get() + 1);
+            bits
+        };
+        let label = TypeId::of::<Label>();
+        let panel = TypeId::of::<Panel>();
+        let layout = TypeId::of::<Layout>();
+        assert_eq!(MASKS.get(label, layout, || compute(0b101)), 0b101);
+        assert_eq!(MASKS.get(label, layout, || compute(0)), 0b101);
+        assert_eq!(MASKS.get(panel, layout, || compute(0b011)), 0b011);
+        assert_eq!(MASKS.get(label, panel, || compute(0b1)), 0b1);
+        assert_eq!(MASKS.get(panel, layout, || compute(0)), 0b011);
+        assert_eq!(computed.get(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn objects_of_one_type() {
+        // Objects whose casts depend on their state are probed, so each gets its own mask.
+        let measured = Composite::new().with::<dyn Measure>(Box::new(Label));
+        let painted = Composite::new()
+            .with::<dyn Paint>(Box::new(Label))
+            .with::<dyn Arrange>(Box::new(Panel));
+        assert_eq!(
+            measured.to_downcast_trait().capabilities::<Layout>().bits(),
+            0b001
+        );
+        assert_eq!(
+            painted.to_downcast_trait().capabilities::<Layout>().bits(),
+            0b110
+        );
+
+        struct Wrapper {
+            inner: Box<dyn DowncastTrait>,
+        }
+        impl DowncastTrait for Wrapper {
+            downcast_trait_impl_convert_to!(delegate = self.inner);
+        }
+        let label = Wrapper {
+            inner: Box::new(Label),
+        };
+        let panel = Wrapper {
+            inner: Box::new(Panel),
+        };
+        assert_eq!(
+            label.to_downcast_trait().capabilities::<Layout>().bits(),
+            0b101
+        );
+        assert_eq!(
+            panel.to_downcast_trait().capabilities::<Layout>().bits(),
+            0b011
+        );
+    }
+}
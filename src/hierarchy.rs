@@ -0,0 +1,412 @@
+//! Casts to the supertraits of a listed trait.
+//!
+//! The relationships between traits are declared once with
+//! [downcast_trait_hierarchy](../macro.downcast_trait_hierarchy.html), which implements
+//! [Supertraits] for the subtrait object type. When a cast is not in the list of an object, every
+//! listed trait is probed for a declared supertrait matching the request. Listed traits without a
+//! declaration fall back to [NoSupertraits], as inherent functions of [SupertraitProbe] are only
+//! found when the `Supertraits` bound holds.
+use core::{any::TypeId, marker::PhantomData, pin::Pin};
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, rc::Rc};
+
+#[cfg(feature = "alloc")]
+use crate::Provided;
+use crate::TraitSlot;
+
+/// Answers casts to the declared supertraits of the trait object type `Self`, for an object of
+/// type `S` implementing it.
+#[doc(hidden)]
+pub trait Supertraits<S> {
+    fn answers(target: TypeId) -> bool;
+    fn provide_ref<'s>(value: &'s S, slot: &mut TraitSlot<'s>);
+    fn provide_mut<'s>(value: &'s mut S, slot: &mut TraitSlot<'s>);
+    fn provide_pin<'s>(value: Pin<&'s mut S>, slot: &mut TraitSlot<'s>);
+    #[cfg(feature = "alloc")]
+    fn provide_box<'s>(value: Box<S>, slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Box<S>>;
+    #[cfg(feature = "alloc")]
+    fn provide_pin_box<'s>(value: Pin<Box<S>>, slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Pin<Box<S>>>;
+    #[cfg(feature = "alloc")]
+    fn provide_rc<'s>(value: Rc<S>, slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Rc<S>>;
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn provide_arc<'s>(value: Arc<S>, slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Arc<S>>;
+}
+
+/// Looks up the declared supertraits of the trait object type `T` for an object of type `S`.
+#[doc(hidden)]
+pub struct SupertraitProbe<T: ?Sized, S>(PhantomData<S>, PhantomData<T>);
+
+impl<T: ?Sized + Supertraits<S>, S> SupertraitProbe<T, S> {
+    pub fn answers(target: TypeId) -> bool {
+        T::answers(target)
+    }
+
+    pub fn provide_ref<'s>(value: &'s S, slot: &mut TraitSlot<'s>) {
+        T::provide_ref(value, slot)
+    }
+
+    pub fn provide_mut<'s>(value: &'s mut S, slot: &mut TraitSlot<'s>) {
+        T::provide_mut(value, slot)
+    }
+
+    pub fn provide_pin<'s>(value: Pin<&'s mut S>, slot: &mut TraitSlot<'s>) {
+        T::provide_pin(value, slot)
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn provide_box<'s>(
+        value: Box<S>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Box<S>> {
+        T::provide_box(value, slot)
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn provide_pin_box<'s>(
+        value: Pin<Box<S>>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Pin<Box<S>>> {
+        T::provide_pin_box(value, slot)
+    }
+
+    #[cfg(feature = "alloc")]
+    pub fn provide_rc<'s>(
+        value: Rc<S>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Rc<S>> {
+        T::provide_rc(value, slot)
+    }
+
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub fn provide_arc<'s>(
+        value: Arc<S>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Arc<S>> {
+        T::provide_arc(value, slot)
+    }
+}
+
+/// The functions of [SupertraitProbe] for trait object types without declared supertraits.
+#[doc(hidden)]
+pub trait NoSupertraits<S> {
+    fn answers(_target: TypeId) -> bool {
+        false
+    }
+    fn provide_ref<'s>(_value: &'s S, _slot: &mut TraitSlot<'s>) {}
+    fn provide_mut<'s>(_value: &'s mut S, _slot: &mut TraitSlot<'s>) {}
+    fn provide_pin<'s>(_value: Pin<&'s mut S>, _slot: &mut TraitSlot<'s>) {}
+    #[cfg(feature = "alloc")]
+    fn provide_box<'s>(value: Box<S>, _slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Box<S>> {
+        Err(value)
+    }
+    #[cfg(feature = "alloc")]
+    fn provide_pin_box<'s>(value: Pin<Box<S>>, _slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Pin<Box<S>>> {
+        Err(value)
+    }
+    #[cfg(feature = "alloc")]
+    fn provide_rc<'s>(value: Rc<S>, _slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Rc<S>> {
+        Err(value)
+    }
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn provide_arc<'s>(value: Arc<S>, _slot: &mut TraitSlot<'s>) -> Result<Provided<'s>, Arc<S>> {
+        Err(value)
+    }
+}
+
+impl<T: ?Sized, S> NoSupertraits<S> for SupertraitProbe<T, S> {}
+
+/// This macro declares the supertraits of a trait, so that objects listing the trait with
+/// [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html) can also be cast
+/// to its supertraits:
+/// ```
+/// use downcast_trait::prelude::*;
+/// trait Widget {}
+/// trait Container: Widget {}
+/// trait ScrollContainer: Container {}
+/// downcast_trait_hierarchy!(dyn Container: dyn Widget);
+/// downcast_trait_hierarchy!(dyn ScrollContainer: dyn Container);
+///
+/// struct Window;
+/// impl Widget for Window {}
+/// impl Container for Window {}
+/// impl ScrollContainer for Window {}
+/// impl DowncastTrait for Window {
+///     downcast_trait_impl_convert_to!(dyn ScrollContainer);
+/// }
+/// assert!(downcast_trait!(dyn Container, Window.to_downcast_trait()).is_some());
+/// assert!(downcast_trait!(dyn Widget, Window.to_downcast_trait()).is_some());
+/// ```
+///
+/// The supertraits of the supertraits are followed too. A declaration is needed once per trait,
+/// in the crate that defines the subtrait. The supertraits are not included in
+/// [supported_traits](trait.DowncastTrait.html#method.supported_traits).
+#[macro_export]
+macro_rules! downcast_trait_hierarchy
+{
+    (dyn $sub:path : $(dyn $super:path),+ $(,)?) => {
+        impl<S: $sub + 'static> $crate::__private::Supertraits<S> for dyn $sub {
+            fn answers(target: $crate::__private::TypeId) -> bool {
+                #[allow(unused_imports)]
+                use $crate::__private::NoSupertraits as _;
+                $(
+                target == $crate::__private::TypeId::of::<dyn $super>()
+                    || $crate::__private::SupertraitProbe::<dyn $super, S>::answers(target)
+                )||+
+            }
+            fn provide_ref<'s>(value: &'s S, slot: &mut $crate::TraitSlot<'s>) {
+                #[allow(unused_imports)]
+                use $crate::__private::NoSupertraits as _;
+                $(
+                if slot.target() == $crate::__private::TypeId::of::<dyn $super>() {
+                    slot.provide_ref::<dyn $super>(value);
+                    slot.provide_ptr::<dyn $super, S>(value, |this| this)
+                } else
+                )+
+                {
+                    $(
+                    if $crate::__private::SupertraitProbe::<dyn $super, S>::answers(slot.target()) {
+                        return $crate::__private::SupertraitProbe::<dyn $super, S>::provide_ref(value, slot);
+                    }
+                    )+
+                }
+            }
+            fn provide_mut<'s>(value: &'s mut S, slot: &mut $crate::TraitSlot<'s>) {
+                #[allow(unused_imports)]
+                use $crate::__private::NoSupertraits as _;
+                $(
+                if slot.target() == $crate::__private::TypeId::of::<dyn $super>() {
+                    slot.provide_mut::<dyn $super>(value)
+                } else
+                )+
+                {
+                    $(
+                    if $crate::__private::SupertraitProbe::<dyn $super, S>::answers(slot.target()) {
+                        return $crate::__private::SupertraitProbe::<dyn $super, S>::provide_mut(value, slot);
+                    }
+                    )+
+                }
+            }
+            fn provide_pin<'s>(value: $crate::__private::Pin<&'s mut S>, slot: &mut $crate::TraitSlot<'s>) {
+                #[allow(unused_imports)]
+                use $crate::__private::NoSupertraits as _;
+                $(
+                if slot.target() == $crate::__private::TypeId::of::<dyn $super>() {
+                    return slot.provide_pin::<dyn $super>(value);
+                }
+                if $crate::__private::SupertraitProbe::<dyn $super, S>::answers(slot.target()) {
+                    return $crate::__private::SupertraitProbe::<dyn $super, S>::provide_pin(value, slot);
+                }
+                )+
+            }
+            $crate::__downcast_trait_hierarchy_owned!($($super),+);
+        }
+    };
+}
+
+/// This macro is used internally by [downcast_trait_hierarchy](macro.downcast_trait_hierarchy.html)
+/// to answer owned casts to the supertraits.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "alloc")]
+macro_rules! __downcast_trait_hierarchy_owned
+{
+    ($($super:path),+) => {
+        $crate::__downcast_trait_hierarchy_owned!(@owned provide_box, $crate::__private::Box<S>, $($super),+);
+        $crate::__downcast_trait_hierarchy_owned!(
+            @owned provide_pin_box, $crate::__private::Pin<$crate::__private::Box<S>>, $($super),+
+        );
+        $crate::__downcast_trait_hierarchy_owned!(@owned provide_rc, $crate::__private::Rc<S>, $($super),+);
+        $crate::__downcast_trait_hierarchy_owned!(
+            @owned #[cfg(target_has_atomic = "ptr")] provide_arc, $crate::__private::Arc<S>, $($super),+
+        );
+    };
+    (@owned $(#[$attr:meta])* $provide:ident, $value:ty, $($super:path),+) => {
+        $(#[$attr])*
+        fn $provide<'s>(value: $value, slot: &mut $crate::TraitSlot<'s>)
+            -> $crate::__private::Result<$crate::Provided<'s>, $value>
+        {
+            #[allow(unused_imports)]
+            use $crate::__private::NoSupertraits as _;
+            $(
+            if slot.target() == $crate::__private::TypeId::of::<dyn $super>() {
+                return slot.$provide::<dyn $super, _>(value, |value| value);
+            }
+            if $crate::__private::SupertraitProbe::<dyn $super, S>::answers(slot.target()) {
+                return $crate::__private::SupertraitProbe::<dyn $super, S>::$provide(value, slot);
+            }
+            )+
+            $crate::__private::Err(value)
+        }
+    };
+}
+
+/// This macro is used internally by [downcast_trait_hierarchy](macro.downcast_trait_hierarchy.html)
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "alloc"))]
+macro_rules! __downcast_trait_hierarchy_owned
+{
+    ($($super:path),+) => {};
+}
+
+/// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+/// to answer a cast that is not in the list, with a declared supertrait of a listed trait or by
+/// forwarding it to the delegate field.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_miss
+{
+    (ref $this:ident, $slot:ident, [$($target:ty),*], $delegate:tt) => {{
+        #[allow(unused_imports)]
+        use $crate::__private::NoSupertraits as _;
+        $(
+        if $crate::__private::SupertraitProbe::<$target, Self>::answers($slot.target()) {
+            $crate::__private::SupertraitProbe::<$target, Self>::provide_ref($this, $slot)
+        } else
+        )*
+        {
+            $crate::__downcast_trait_delegate!(ref $this, $slot, $delegate)
+        }
+    }};
+    (mut $this:ident, $slot:ident, [$($target:ty),*], $delegate:tt) => {{
+        #[allow(unused_imports)]
+        use $crate::__private::NoSupertraits as _;
+        $(
+        if $crate::__private::SupertraitProbe::<$target, Self>::answers($slot.target()) {
+            $crate::__private::SupertraitProbe::<$target, Self>::provide_mut($this, $slot)
+        } else
+        )*
+        {
+            $crate::__downcast_trait_delegate!(mut $this, $slot, $delegate)
+        }
+    }};
+    (box $this:ident, $slot:ident, [$($target:ty),*], $delegate:tt) => {{
+        #[allow(unused_imports)]
+        use $crate::__private::NoSupertraits as _;
+        $(
+        if $crate::__private::SupertraitProbe::<$target, Self>::answers($slot.target()) {
+            match $crate::__private::SupertraitProbe::<$target, Self>::provide_box($this, $slot) {
+                $crate::__private::Ok(provided) => $crate::__private::Ok(provided),
+                $crate::__private::Err(this) => $crate::__private::Err(this),
+            }
+        } else
+        )*
+        {
+            $crate::__downcast_trait_delegate!(box $this, $slot, $delegate)
+        }
+    }};
+    (pin $this:ident, $slot:ident, [$($target:ty),*]) => {{
+        #[allow(unused_imports)]
+        use $crate::__private::NoSupertraits as _;
+        $(
+        if $crate::__private::SupertraitProbe::<$target, Self>::answers($slot.target()) {
+            $crate::__private::SupertraitProbe::<$target, Self>::provide_pin($this, $slot)
+        } else
+        )*
+        {}
+    }};
+    ($provide:ident $this:ident, $slot:ident, [$($target:ty),*]) => {{
+        #[allow(unused_imports)]
+        use $crate::__private::NoSupertraits as _;
+        $(
+        if $crate::__private::SupertraitProbe::<$target, Self>::answers($slot.target()) {
+            match $crate::__private::SupertraitProbe::<$target, Self>::$provide($this, $slot) {
+                $crate::__private::Ok(provided) => $crate::__private::Ok(provided),
+                $crate::__private::Err(this) => $crate::__private::Err(this),
+            }
+        } else
+        )*
+        {
+            $crate::__private::Err($this)
+        }
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use crate::{
+        downcast_trait, downcast_trait_impl_convert_to, downcast_trait_mut, DowncastTrait,
+    };
+    #[cfg(feature = "alloc")]
+    use std::boxed::Box;
+
+    trait Widget {
+        fn id(&self) -> u32;
+    }
+    trait Named {
+        fn name(&self) -> &str;
+    }
+    trait Container: Widget {
+        fn len(&self) -> usize;
+    }
+    trait ScrollContainer: Container + Named {
+        fn scroll(&mut self, by: u32) -> u32;
+    }
+    downcast_trait_hierarchy!(dyn Container: dyn Widget);
+    downcast_trait_hierarchy!(dyn ScrollContainer: dyn Container, dyn Named);
+
+    struct Window {
+        offset: u32,
+    }
+    impl Widget for Window {
+        fn id(&self) -> u32 {
+            7
+        }
+    }
+    impl Named for Window {
+        fn name(&self) -> &str {
+            "window"
+        }
+    }
+    impl Container for Window {
+        fn len(&self) -> usize {
+            2
+        }
+    }
+    impl ScrollContainer for Window {
+        fn scroll(&mut self, by: u32) -> u32 {
+            self.offset += by;
+            self.offset
+        }
+    }
+    impl DowncastTrait for Window {
+        downcast_trait_impl_convert_to!(dyn ScrollContainer);
+    }
+
+    #[test]
+    fn supertrait_casts() {
+        let mut window = Window { offset: 0 };
+        let object = window.to_downcast_trait_mut();
+        assert_eq!(downcast_trait!(dyn Container, object).unwrap().len(), 2);
+        assert_eq!(downcast_trait!(dyn Widget, object).unwrap().id(), 7);
+        assert_eq!(downcast_trait!(dyn Named, object).unwrap().name(), "window");
+        assert!(downcast_trait_mut!(dyn Widget, object).is_some());
+        assert_eq!(
+            downcast_trait_mut!(dyn ScrollContainer, object)
+                .unwrap()
+                .scroll(3),
+            3
+        );
+        assert!(downcast_trait!(dyn core::fmt::Debug, object).is_none());
+        assert_eq!(object.supported_traits().len(), 1);
+        let pinned = core::pin::Pin::new(&mut window);
+        assert_eq!(crate::downcast_trait_pin!(dyn Widget, pinned).unwrap().id(), 7);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn supertrait_owned_casts() {
+        let boxed: Box<dyn DowncastTrait> = Box::new(Window { offset: 0 });
+        let widget = crate::downcast_trait_box!(dyn Widget, boxed).ok().unwrap();
+        assert_eq!(widget.id(), 7);
+        let shared: std::rc::Rc<dyn DowncastTrait> = std::rc::Rc::new(Window { offset: 0 });
+        assert!(crate::downcast_trait_rc!(dyn Container, shared).is_ok());
+        let shared: std::sync::Arc<dyn DowncastTrait> = std::sync::Arc::new(Window { offset: 0 });
+        assert!(crate::downcast_trait_arc!(dyn Named, shared).is_ok());
+        let pinned = crate::downcast_trait_pin_box!(dyn Container, Box::pin(Window { offset: 0 }));
+        assert_eq!(pinned.ok().unwrap().len(), 2);
+    }
+}
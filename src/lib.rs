@@ -1,12 +1,12 @@
 #![cfg_attr(not(feature = "std"), no_std)]
-#![allow(unused_imports)]
 //!
 //! Downcast trait: A module to support downcasting dyn traits using [core::any].
 //! This trait is similar to [intertrait](https://crates.io/crates/intertrait), but does not require
 //! to make a hashtable or any fancy linker magic. For certain cases all casting is optimized away.
 //!
-//! This crate uses transmute (which is generally considered unsafe rust) to pass an unknown type
-//!  as a return value from a function, but the value is then transmuted back to the original type.
+//! The trait objects are passed out of the implementing struct through a [TraitSlot], a type
+//! erased out-parameter keyed by the `TypeId` of the requested trait. The slot only accepts a value
+//! of exactly the requested type, so no pointer is ever reinterpreted as another trait object.
 //!
 //! Downcast traits enables callers to convert dyn objects that implement the
 //! DowncastTrait trait to any trait that is supported by the struct implementing the trait.
@@ -22,9 +22,7 @@
 //!   implement container.
 //!
 //! ```
-//! #[macro_use] extern crate downcast_trait;
-//! use downcast_trait::DowncastTrait;
-//! use core::{any::{Any, TypeId}, mem};
+//! use downcast_trait::prelude::*;
 //! trait Widget: DowncastTrait {}
 //! trait Container: Widget {
 //!     fn enumerate_widget_leaves_recursive(&self) -> Vec<&Box<dyn Widget>>;
@@ -52,9 +50,147 @@
 //!     downcast_trait_impl_convert_to!(dyn Container);
 //! }
 //! ```
-use core::{
-    any::{Any, TypeId},
-    mem,
+//!
+//! With the `derive` feature enabled, the implementation can be derived instead:
+//! ```ignore
+//! #[derive(DowncastTrait)]
+//! #[downcast(dyn Container)]
+//! struct Window {
+//!     sub_widgets: Vec<Box<dyn Widget>>,
+//! }
+//! ```
+//!
+//! Shared and mutable casts work in `no_std` crates. The `alloc` feature adds `Box` and `Rc`
+//! casts, and `Arc` casts on targets with pointer sized atomics. The `std` feature implies
+//! `alloc`, and adds [CastRegistry], the cached lookup tables and the cast list checks. Without
+//! `std` long cast lists are checked one trait at a time.
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+use core::{any::TypeId, pin::Pin, ptr::NonNull};
+
+mod borrowed;
+#[cfg(feature = "std")]
+mod check;
+#[cfg(feature = "alloc")]
+mod composite;
+mod conditional;
+mod delegate;
+mod descriptor;
+mod family;
+mod hierarchy;
+mod lookup;
+mod matching;
+mod pointer;
+#[cfg(feature = "std")]
+mod registry;
+mod slot;
+mod upcast;
+pub use borrowed::{BorrowedDowncastTrait, BorrowedTraitSlot, EraseLifetime};
+#[cfg(feature = "alloc")]
+pub use composite::Composite;
+pub use conditional::{Coercions, ConditionalCast};
+pub use descriptor::TraitDescriptor;
+pub use family::{Capabilities, CapabilityFamily};
+pub use pointer::CastablePointer;
+#[cfg(feature = "std")]
+pub use registry::CastRegistry;
+pub use slot::{Provided, TraitSlot};
+#[cfg(feature = "derive")]
+pub use downcast_trait_derive::DowncastTrait;
+
+/// The items used by the exported macros, so expansions do not depend on what is in scope at the
+/// call site.
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(feature = "std")]
+    pub use crate::check::{check_cast_list, Candidate};
+    #[cfg(feature = "alloc")]
+    pub use crate::delegate::{BoxIntoDowncastBox, IntoDowncastBox, Refused};
+    pub use crate::conditional::{provide_mut_if, provide_pin_if, provide_ref_if, OwnedCoercions};
+    #[cfg(feature = "alloc")]
+    pub use crate::conditional::{provide_box_if, provide_pin_box_if, provide_rc_if};
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub use crate::conditional::provide_arc_if;
+    pub use crate::descriptor::select_supported;
+    pub use crate::family::{mask_of, object_mask, MaskCache};
+    pub use crate::hierarchy::{NoSupertraits, SupertraitProbe, Supertraits};
+    pub use crate::lookup::{LookupTable, LOOKUP_THRESHOLD};
+    pub use crate::matching::TraitSource;
+    pub use core::marker::PhantomData;
+    pub use crate::pointer::cast_pointer;
+    pub use crate::slot::{cast_mut, cast_pin, cast_ref};
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub use crate::slot::cast_arc;
+    #[cfg(feature = "alloc")]
+    pub use crate::slot::{answers_owned, cast_box, cast_pin_box, cast_rc};
+    pub use core::any::{type_name, TypeId};
+    pub use core::option::Option::{self, None, Some};
+    pub use core::pin::Pin;
+    pub use core::result::Result::{self, Err, Ok};
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub use alloc::sync::Arc;
+    #[cfg(feature = "alloc")]
+    pub use alloc::{boxed::Box, rc::Rc};
+}
+
+/// The traits, types and macros needed to implement and use [DowncastTrait], for importing with
+/// `use downcast_trait::prelude::*;`. No other imports are needed by the macros, in any edition:
+/// ```edition2018
+/// use downcast_trait::prelude::*;
+/// trait Container {}
+/// struct Window;
+/// impl Container for Window {}
+/// impl DowncastTrait for Window {
+///     downcast_trait_impl_convert_to!(dyn Container);
+/// }
+/// assert!(downcast_trait!(dyn Container, Window.to_downcast_trait()).is_some());
+/// ```
+/// ```edition2021
+/// # use downcast_trait::prelude::*;
+/// # trait Container {}
+/// # struct Window;
+/// # impl Container for Window {}
+/// # impl DowncastTrait for Window {
+/// #     downcast_trait_impl_convert_to!(dyn Container);
+/// # }
+/// # assert!(downcast_trait!(dyn Container, Window.to_downcast_trait()).is_some());
+/// ```
+/// ```edition2024
+/// # use downcast_trait::prelude::*;
+/// # trait Container {}
+/// # struct Window;
+/// # impl Container for Window {}
+/// # impl DowncastTrait for Window {
+/// #     downcast_trait_impl_convert_to!(dyn Container);
+/// # }
+/// # assert!(downcast_trait!(dyn Container, Window.to_downcast_trait()).is_some());
+/// ```
+pub mod prelude {
+    #[cfg(feature = "std")]
+    pub use crate::{assert_cast_list_complete, downcast_trait_register, CastRegistry};
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub use crate::{cast_arc_weak, downcast_trait_arc};
+    #[cfg(feature = "alloc")]
+    pub use crate::{
+        cast_rc_weak, downcast_trait_box, downcast_trait_pin_box, downcast_trait_rc, CastChain,
+        Composite,
+    };
+    pub use crate::{
+        cast_ptr, downcast_trait, downcast_trait_coercions, downcast_trait_erase_lifetime,
+        downcast_trait_family,
+        downcast_trait_hierarchy, downcast_trait_impl_convert_to,
+        downcast_trait_impl_convert_to_borrowed, downcast_trait_impl_for, downcast_trait_mut,
+        downcast_trait_pin, downcast_trait_ptr, match_trait, BorrowedDowncastTrait, Capabilities,
+        CapabilityFamily, CastTarget, CastablePointer, Coercions, ConditionalCast, DowncastTrait,
+    };
+}
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+use alloc::sync::{self, Arc};
+#[cfg(feature = "alloc")]
+use alloc::{
+    boxed::Box,
+    rc::{self, Rc},
 };
 
 /// This trait should be implemented by any structs that or traits that should be downcastable
@@ -63,28 +199,433 @@ use core::{
 /// ```ignore
 /// trait Widget: DowncastTrait {}
 /// ```
-pub trait DowncastTrait {
-    /// # Safety
-    /// This function is called by the [downcast_trait](macro.downcast_trait.html) macro and should
-    /// not be accessed directly.
-    unsafe fn convert_to_trait(&self, trait_id: TypeId) -> Option<&(dyn Any)>;
-    /// # Safety
-    /// This function is called by the [downcast_trait_mut](macro.downcast_trait_mut.html) macro
-    /// and should not be accessed directly.
-    unsafe fn convert_to_trait_mut(&mut self, trait_id: TypeId) -> Option<&mut (dyn Any)>;
-    /// # Safety
-    /// This function is called by the [downcast_trait_mut](macro.downcast_trait_mut.html) macro
-    /// and should not be accessed directly.
-#[cfg(feature = "std")]
-    unsafe fn convert_to_trait_box(self: Box<Self>, trait_id: TypeId) -> Option<Box<dyn Any>>;
+///
+/// The functions can also be written by hand when the casts need custom logic, and no `unsafe`
+/// is needed to do so. An implementation answers through the [TraitSlot] it is given, which only
+/// accepts a value of the requested type, and for shared and mutable casts only references that
+/// live as long as the borrow of the object:
+/// ```
+/// # use core::any::TypeId;
+/// # use downcast_trait::{downcast_trait, DowncastTrait, TraitSlot};
+/// trait Container {}
+/// struct Scrolled(u32);
+/// impl Container for Scrolled {}
+/// struct Window {
+///     content: Scrolled,
+///     enabled: bool,
+/// }
+/// impl DowncastTrait for Window {
+///     fn convert_to_trait<'s>(&'s self, slot: &mut TraitSlot<'s>) {
+///         if slot.target() == TypeId::of::<dyn Container>() && self.enabled {
+///             slot.provide_ref::<dyn Container>(&self.content);
+///         }
+///     }
+///     fn convert_to_trait_mut<'s>(&'s mut self, slot: &mut TraitSlot<'s>) {
+///         if slot.target() == TypeId::of::<dyn Container>() && self.enabled {
+///             slot.provide_mut::<dyn Container>(&mut self.content);
+///         }
+///     }
+/// }
+///
+/// let window = Window { content: Scrolled(0), enabled: true };
+/// assert!(downcast_trait!(dyn Container, window.to_downcast_trait()).is_some());
+/// ```
+///
+/// Only the casts the type answers have to be written. Every other function has a default, and
+/// the default `convert_to_trait*` functions only answer casts to the concrete type, so the
+/// boxed, `Rc` and `Arc` casts of `Window` above can only downcast it to `Window`. A function that
+/// is written by hand replaces that default, and answers the concrete type only if it does so
+/// itself.
+///
+/// An implementation that answers with the object itself can also answer [cast_ptr] and
+/// [downcast_trait_ptr](macro.downcast_trait_ptr.html), with
+/// [provide_ptr](struct.TraitSlot.html#method.provide_ptr).
+///
+/// An answer that does not borrow from the object is rejected by the compiler:
+/// ```compile_fail
+/// # use downcast_trait::{DowncastTrait, TraitSlot};
+/// # trait Container {}
+/// # struct Scrolled(u32);
+/// # impl Container for Scrolled {}
+/// struct Window;
+/// impl DowncastTrait for Window {
+///     fn convert_to_trait<'s>(&'s self, slot: &mut TraitSlot<'s>) {
+///         let temporary = Scrolled(0);
+///         slot.provide_ref::<dyn Container>(&temporary);
+///     }
+/// }
+/// ```
+pub trait DowncastTrait: upcast::AsDowncastTrait {
+    /// Answers a shared cast by passing `self`, or a part of it, to one of the `provide_*`
+    /// functions of `slot`. This function is called by the [downcast_trait](macro.downcast_trait.html)
+    /// macro and should not be accessed directly.
+    fn convert_to_trait<'s>(&'s self, slot: &mut TraitSlot<'s>) {
+        self.provide_self(slot)
+    }
+    /// Answers a mutable cast by passing `self`, or a part of it, to one of the `provide_*`
+    /// functions of `slot`. This function is called by the
+    /// [downcast_trait_mut](macro.downcast_trait_mut.html) macro and should not be accessed
+    /// directly.
+    fn convert_to_trait_mut<'s>(&'s mut self, slot: &mut TraitSlot<'s>) {
+        self.provide_self_mut(slot)
+    }
+    /// Answers a pinned mutable cast by passing the pinned `self` to
+    /// [provide_pin](struct.TraitSlot.html#method.provide_pin). This function is called by the
+    /// [downcast_trait_pin](macro.downcast_trait_pin.html) macro and should not be accessed
+    /// directly.
+    fn convert_to_trait_pin<'s>(self: Pin<&'s mut Self>, slot: &mut TraitSlot<'s>) {
+        self.provide_self_pin(slot)
+    }
+    /// This function is called by the [downcast_trait_box](macro.downcast_trait_box.html) macro
+    /// and should not be accessed directly. It returns the [Provided] proof of the slot after
+    /// providing the box to it, and hands the box back as `Err` if the requested trait is not
+    /// supported.
+    #[cfg(feature = "alloc")]
+    fn convert_to_trait_box<'s>(
+        self: Box<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Box<dyn DowncastTrait>> {
+        self.provide_self_box(slot)
+    }
+    /// This function is called by the [downcast_trait_pin_box](macro.downcast_trait_pin_box.html)
+    /// macro and should not be accessed directly. It returns the [Provided] proof of the slot
+    /// after providing the pinned box to it, and hands the box back as `Err` if the requested trait
+    /// is not supported.
+    #[cfg(feature = "alloc")]
+    fn convert_to_trait_pin_box<'s>(
+        self: Pin<Box<Self>>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Pin<Box<dyn DowncastTrait>>> {
+        self.provide_self_pin_box(slot)
+    }
+    /// This function is called by the [downcast_trait_rc](macro.downcast_trait_rc.html) macro
+    /// and should not be accessed directly. It returns the [Provided] proof of the slot after
+    /// providing the `Rc` to it, and hands the `Rc` back as `Err` if the requested trait is not
+    /// supported.
+    #[cfg(feature = "alloc")]
+    fn convert_to_trait_rc<'s>(
+        self: Rc<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Rc<dyn DowncastTrait>> {
+        self.provide_self_rc(slot)
+    }
+    /// This function is called by the [downcast_trait_arc](macro.downcast_trait_arc.html) macro
+    /// and should not be accessed directly. It returns the [Provided] proof of the slot after
+    /// providing the `Arc` to it, and hands the `Arc` back as `Err` if the requested trait is not
+    /// supported.
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn convert_to_trait_arc<'s>(
+        self: Arc<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Arc<dyn DowncastTrait>> {
+        self.provide_self_arc(slot)
+    }
     /// This function is used to cast any implementer of this trait to a DowncastTrait
-    fn to_downcast_trait(&self) -> &dyn DowncastTrait;
+    fn to_downcast_trait(&self) -> &dyn DowncastTrait {
+        self.as_downcast_trait()
+    }
+    /// Returns the `TypeId` of the concrete type implementing this trait
+    fn type_id(&self) -> TypeId {
+        self.concrete_type_id()
+    }
+    /// Returns the name of the concrete type implementing this trait
+    fn type_name(&self) -> &'static str {
+        core::any::type_name::<Self>()
+    }
+    /// Returns a description of every trait this object can be cast to. A trait listed with bounds,
+    /// such as `dyn Container + Send`, is only described in that form, although the cast to
+    /// `dyn Container` succeeds as well. By default this is [listed_traits](#method.listed_traits).
+    fn supported_traits(&self) -> &'static [TraitDescriptor] {
+        self.concrete_listed_traits()
+    }
+    /// Returns a description of every trait listed for this type, without an instance of it. This
+    /// is the same list as [supported_traits](#method.supported_traits), and is empty for types
+    /// whose traits are only known at runtime.
+    fn listed_traits() -> &'static [TraitDescriptor]
+    where
+        Self: Sized,
+    {
+        &[]
+    }
+    /// Returns the bits of the traits in `members`, the traits of the family with the `TypeId`
+    /// `family`, that this object can be cast to, or None to have the object probed. This is used
+    /// by [Capabilities::of_object] and should not be accessed directly. The
+    /// [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html) macro computes
+    /// it once for each type from the listed traits, and adds the mask of a `delegate` field.
+    fn capability_mask(&self, _family: TypeId, _members: &[TraitDescriptor]) -> Option<u64> {
+        None
+    }
     /// This function is used to cast any implementer of this trait to a mut DowncastTrait
-    fn to_downcast_trait_mut(&mut self) -> &mut dyn DowncastTrait;
-    /// This function is used to cast any implementer of this trait to a Box<DowncastTrait>
-#[cfg(feature = "std")]
-    fn to_downcast_trait_box(self: Box<Self>) -> Box<dyn DowncastTrait>;
-//    fn to_downcast_trait_box(&self) -> Box<&dyn DowncastTrait>;
+    fn to_downcast_trait_mut(&mut self) -> &mut dyn DowncastTrait {
+        self.as_downcast_trait_mut()
+    }
+    /// This function is used to cast any implementer of this trait to a `Box<DowncastTrait>`
+    #[cfg(feature = "alloc")]
+    fn to_downcast_trait_box(self: Box<Self>) -> Box<dyn DowncastTrait> {
+        self.into_downcast_trait_box()
+    }
+    /// This function is used to cast any implementer of this trait to a `Rc<DowncastTrait>`
+    #[cfg(feature = "alloc")]
+    fn to_downcast_trait_rc(self: Rc<Self>) -> Rc<dyn DowncastTrait> {
+        self.into_downcast_trait_rc()
+    }
+    /// This function is used to cast any implementer of this trait to an `Arc<DowncastTrait>`
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn to_downcast_trait_arc(self: Arc<Self>) -> Arc<dyn DowncastTrait> {
+        self.into_downcast_trait_arc()
+    }
+    /// Moves the object out of a uniquely owned `Rc` into a `Box`, or hands the `Rc` back if
+    /// there are other strong references to it.
+    #[cfg(feature = "alloc")]
+    fn rc_into_downcast_trait_box(
+        self: Rc<Self>,
+    ) -> Result<Box<dyn DowncastTrait>, Rc<dyn DowncastTrait>> {
+        self.unwrap_rc()
+    }
+    /// Moves the object out of a uniquely owned `Arc` into a `Box`, or hands the `Arc` back if
+    /// there are other strong references to it.
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn arc_into_downcast_trait_box(
+        self: Arc<Self>,
+    ) -> Result<Box<dyn DowncastTrait>, Arc<dyn DowncastTrait>> {
+        self.unwrap_arc()
+    }
+}
+
+/// Marker trait for trait object types that can be used with the generic cast methods
+/// ([cast_ref](trait.DowncastTrait.html#method.cast_ref),
+/// [cast_mut](trait.DowncastTrait.html#method.cast_mut) and
+/// [cast_box](trait.DowncastTrait.html#method.cast_box)). It is implemented once per trait:
+/// ```
+/// # use downcast_trait::{CastTarget, DowncastTrait};
+/// trait Container {}
+/// impl CastTarget for dyn Container {}
+///
+/// fn find_all<T: ?Sized + CastTarget>(items: &[Box<dyn DowncastTrait>]) -> Vec<&T> {
+///     items.iter().filter_map(|item| item.cast_ref::<T>()).collect()
+/// }
+/// ```
+pub trait CastTarget: 'static {}
+
+impl CastTarget for dyn core::fmt::Debug {}
+impl CastTarget for dyn core::fmt::Display {}
+
+impl<'a> dyn DowncastTrait + 'a {
+    /// Returns true if the concrete type of this object is `T`.
+    pub fn is<T: 'static>(&self) -> bool {
+        self.type_id() == TypeId::of::<T>()
+    }
+
+    /// Casts this object to a reference to its concrete type `T`, e.g.
+    /// `obj.downcast_ref::<Window>()`. Returns None if the object is not a `T`.
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        slot::cast_ref(self)
+    }
+
+    /// Casts this object to a mutable reference to its concrete type `T`, e.g.
+    /// `obj.downcast_mut::<Window>()`. Returns None if the object is not a `T`.
+    pub fn downcast_mut<T: 'static>(&mut self) -> Option<&mut T> {
+        slot::cast_mut(self)
+    }
+
+    /// Casts this object to a reference to the trait object type `T`, e.g.
+    /// `obj.cast_ref::<dyn Container>()`. Returns None if the trait is not supported.
+    pub fn cast_ref<T: ?Sized + CastTarget>(&self) -> Option<&T> {
+        slot::cast_ref(self)
+    }
+
+    /// Casts this object to a mutable reference to the trait object type `T`, e.g.
+    /// `obj.cast_mut::<dyn Container>()`. Returns None if the trait is not supported.
+    pub fn cast_mut<T: ?Sized + CastTarget>(&mut self) -> Option<&mut T> {
+        slot::cast_mut(self)
+    }
+
+    /// Casts this pinned object to a pinned mutable reference to the trait object type `T`, e.g.
+    /// `obj.as_mut().cast_pin::<dyn Animation>()`. Returns None if the trait is not supported.
+    pub fn cast_pin<T: ?Sized + CastTarget>(self: Pin<&mut Self>) -> Option<Pin<&mut T>> {
+        slot::cast_pin(self)
+    }
+
+    /// Returns the traits of the family `F` that this object can be cast to, e.g.
+    /// `obj.capabilities::<Layout>()`. See [Capabilities::of_object].
+    pub fn capabilities<F: CapabilityFamily>(&self) -> Capabilities<F> {
+        Capabilities::of_object(self)
+    }
+}
+
+impl dyn DowncastTrait {
+    /// Casts this boxed object to a box of its concrete type `T`. Returns the original box as
+    /// `Err` if the object is not a `T`.
+    #[cfg(feature = "alloc")]
+    pub fn downcast_box<T: 'static>(self: Box<Self>) -> Result<Box<T>, Box<dyn DowncastTrait>> {
+        slot::cast_box(self)
+    }
+
+    /// Casts this reference counted object to an `Rc` of its concrete type `T`. Returns the
+    /// original `Rc` as `Err` if the object is not a `T`.
+    #[cfg(feature = "alloc")]
+    pub fn downcast_rc<T: 'static>(self: Rc<Self>) -> Result<Rc<T>, Rc<dyn DowncastTrait>> {
+        slot::cast_rc(self)
+    }
+
+    /// Casts this atomically reference counted object to an `Arc` of its concrete type `T`.
+    /// Returns the original `Arc` as `Err` if the object is not a `T`.
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub fn downcast_arc<T: 'static>(self: Arc<Self>) -> Result<Arc<T>, Arc<dyn DowncastTrait>> {
+        slot::cast_arc(self)
+    }
+
+    /// Casts this boxed object to a box of the trait object type `T`, e.g.
+    /// `obj.cast_box::<dyn Container>()`. Returns the original box as `Err` if the trait is not
+    /// supported.
+    #[cfg(feature = "alloc")]
+    pub fn cast_box<T: ?Sized + CastTarget>(
+        self: Box<Self>,
+    ) -> Result<Box<T>, Box<dyn DowncastTrait>> {
+        slot::cast_box(self)
+    }
+
+    /// Casts this pinned boxed object to a pinned box of the trait object type `T`, e.g.
+    /// `obj.cast_pin_box::<dyn Animation>()`. Returns the original box as `Err` if the trait is
+    /// not supported.
+    #[cfg(feature = "alloc")]
+    pub fn cast_pin_box<T: ?Sized + CastTarget>(
+        self: Pin<Box<Self>>,
+    ) -> Result<Pin<Box<T>>, Pin<Box<dyn DowncastTrait>>> {
+        slot::cast_pin_box(self)
+    }
+
+    /// Casts this reference counted object to an `Rc` of the trait object type `T`, sharing the
+    /// allocation and reference count. Returns the original `Rc` as `Err` if the trait is not
+    /// supported.
+    #[cfg(feature = "alloc")]
+    pub fn cast_rc<T: ?Sized + CastTarget>(self: Rc<Self>) -> Result<Rc<T>, Rc<dyn DowncastTrait>> {
+        slot::cast_rc(self)
+    }
+
+    /// Casts this atomically reference counted object to an `Arc` of the trait object type `T`,
+    /// sharing the allocation and reference count. Returns the original `Arc` as `Err` if the
+    /// trait is not supported.
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub fn cast_arc<T: ?Sized + CastTarget>(
+        self: Arc<Self>,
+    ) -> Result<Arc<T>, Arc<dyn DowncastTrait>> {
+        slot::cast_arc(self)
+    }
+}
+
+/// Casts a weak reference to a [DowncastTrait] object to a weak reference to `T` that points to
+/// the same allocation. The object is kept alive while it is cast, so the original weak reference
+/// is handed back if it can not be upgraded or if the trait is not supported.
+#[cfg(feature = "alloc")]
+pub fn cast_rc_weak<T: ?Sized + CastTarget>(
+    src: rc::Weak<dyn DowncastTrait>,
+) -> Result<rc::Weak<T>, rc::Weak<dyn DowncastTrait>> {
+    match src.upgrade().map(<dyn DowncastTrait>::cast_rc::<T>) {
+        Some(Ok(strong)) => Ok(Rc::downgrade(&strong)),
+        _ => Err(src),
+    }
+}
+
+/// Casts a weak reference to a [DowncastTrait] object to a weak reference to `T` that points to
+/// the same allocation. The object is kept alive while it is cast, so the original weak reference
+/// is handed back if it can not be upgraded or if the trait is not supported.
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+pub fn cast_arc_weak<T: ?Sized + CastTarget>(
+    src: sync::Weak<dyn DowncastTrait>,
+) -> Result<sync::Weak<T>, sync::Weak<dyn DowncastTrait>> {
+    match src.upgrade().map(<dyn DowncastTrait>::cast_arc::<T>) {
+        Some(Ok(strong)) => Ok(Arc::downgrade(&strong)),
+        _ => Err(src),
+    }
+}
+
+/// Casts a pointer to a [DowncastTrait] object to a pointer to `T`, for objects that are held by
+/// raw pointers such as intrusive lists and handles passed through C callbacks. Returns None if
+/// the trait is not supported.
+/// ```
+/// # use core::ptr::NonNull;
+/// # use downcast_trait::prelude::*;
+/// # trait Container {
+/// #     fn len(&self) -> usize;
+/// # }
+/// # impl CastTarget for dyn Container {}
+/// # struct Window;
+/// # impl Container for Window {
+/// #     fn len(&self) -> usize {
+/// #         2
+/// #     }
+/// # }
+/// # impl DowncastTrait for Window {
+/// #     downcast_trait_impl_convert_to!(dyn Container);
+/// # }
+/// let handle: NonNull<dyn DowncastTrait> = NonNull::from(&mut Window);
+/// let container = unsafe { cast_ptr::<dyn Container>(handle) }.unwrap();
+/// assert_eq!(unsafe { container.as_ref() }.len(), 2);
+/// ```
+///
+/// Stable Rust has no methods on raw pointers to trait objects, so the cast is dispatched through
+/// a shared reference to the object that does not outlive the call. The result is not derived from
+/// it: the object answers with an unsizing coercion, which is applied to `src` itself. The result
+/// keeps the provenance of `src`, and the two may be used in turns like copies of one pointer.
+///
+/// Only the object itself can be answered this way, through [TraitSlot::provide_ptr]. Projected
+/// traits, casts forwarded to a `delegate` field and the parts of a
+/// [Composite](struct.Composite.html) return None, even though a shared cast would answer them,
+/// and can be cast to through a reference instead.
+///
+/// # Safety
+/// `src` must point to a live object, as for a call to [NonNull::as_ref]. The shared reference
+/// the cast is dispatched through is created even though the result is not derived from it, so no
+/// mutable reference to the object, or to a value containing it such as a node of an intrusive
+/// list, may be live during the call. Mutable references created from the result after the call
+/// are fine.
+pub unsafe fn cast_ptr<T: ?Sized + CastTarget>(
+    src: NonNull<dyn DowncastTrait + '_>,
+) -> Option<NonNull<T>> {
+    slot::cast_ptr(src)
+}
+
+/// Tries several owned casts in order, keeping ownership of the object until one of them succeeds.
+/// ```
+/// # use downcast_trait::{CastChain, CastTarget, DowncastTrait};
+/// # trait Container {}
+/// # impl CastTarget for dyn Container {}
+/// # trait Leaf {}
+/// # impl CastTarget for dyn Leaf {}
+/// fn describe(widget: Box<dyn DowncastTrait>) -> Result<&'static str, Box<dyn DowncastTrait>> {
+///     CastChain::new(widget)
+///         .or_cast(|_: Box<dyn Container>| "container")
+///         .or_cast(|_: Box<dyn Leaf>| "leaf")
+///         .finish()
+/// }
+/// ```
+#[cfg(feature = "alloc")]
+pub struct CastChain<R> {
+    state: Result<R, Box<dyn DowncastTrait>>,
+}
+
+#[cfg(feature = "alloc")]
+impl<R> CastChain<R> {
+    /// Starts a chain of casts on `src`.
+    pub fn new(src: Box<dyn DowncastTrait>) -> Self {
+        CastChain { state: Err(src) }
+    }
+
+    /// If no earlier cast has succeeded, tries to cast the object to `T` and passes it to `f`.
+    pub fn or_cast<T: ?Sized + CastTarget>(self, f: impl FnOnce(Box<T>) -> R) -> Self {
+        match self.state {
+            Ok(result) => CastChain { state: Ok(result) },
+            Err(src) => CastChain {
+                state: src.cast_box::<T>().map(f),
+            },
+        }
+    }
+
+    /// Returns the result of the first successful cast, or the original box if none succeeded.
+    pub fn finish(self) -> Result<R, Box<dyn DowncastTrait>> {
+        self.state
+    }
 }
 
 /// This macro can be used to cast a &dyn DowncastTrait to an implemented trait e.g:
@@ -97,15 +638,9 @@ pub trait DowncastTrait {
 /// ```
 #[macro_export]
 macro_rules! downcast_trait {
-    ( dyn $type:path, $src:expr) => {{
-        fn transmute_helper(src: &dyn DowncastTrait) -> Option<&dyn $type> {
-            unsafe {
-                src.convert_to_trait(TypeId::of::<dyn $type>())
-                    .map(|dst| mem::transmute::<&(dyn Any), &(dyn $type)>(dst))
-            }
-        }
-        transmute_helper($src)
-    }};
+    ($target:ty, $src:expr) => {
+        $crate::__private::cast_ref::<$target>($src)
+    };
 }
 
 /// This macro can be used to cast a &dyn mut DowncastTrait to an implemented trait e.g:
@@ -118,134 +653,426 @@ macro_rules! downcast_trait {
 /// ```
 #[macro_export]
 macro_rules! downcast_trait_mut {
-    ( dyn $type:path, $src:expr) => {{
-        fn transmute_helper(src: &mut dyn DowncastTrait) -> Option<&mut dyn $type> {
-            unsafe {
-                src.convert_to_trait_mut(TypeId::of::<dyn $type>())
-                    .map(|dst| mem::transmute::<&mut (dyn Any), &mut (dyn $type)>(dst))
-            }
-        }
-        transmute_helper($src)
-    }};
+    ($target:ty, $src:expr) => {
+        $crate::__private::cast_mut::<$target>($src)
+    };
 }
 
-/// This macro can be used to cast a Box<mut DowncastTrait> to an implemented trait e.g:
+/// This macro can be used to cast a `Box<mut DowncastTrait>` to an implemented trait. If the trait
+/// is not supported, the original box is returned as the error e.g:
 /// ```ignore
-/// if let Some(sub_container) =
-///     downcast_trait_box!(dyn Container, Box::new(sub_widget).to_downcast_trait_box())
+/// match downcast_trait_box!(dyn Container, Box::new(sub_widget).to_downcast_trait_box())
 /// {
-///   //Use downcasted trait
+///   Ok(sub_container) => {} //Use downcasted trait
+///   Err(sub_widget) => {} //Try another trait
 /// }
 /// ```
 #[macro_export]
 macro_rules! downcast_trait_box {
-    ( dyn $type:path, $src:expr) => {{
-        fn transmute_helper(src: Box<dyn DowncastTrait>) -> Option<Box<dyn $type>> {
-            unsafe {
-                src.convert_to_trait_box(TypeId::of::<dyn $type>())
-                    .map(|dst| mem::transmute::<Box<dyn Any>, Box<dyn $type>>(dst))
+    ($target:ty, $src:expr) => {
+        $crate::__private::cast_box::<$target>($src)
+    };
+}
+
+/// This macro can be used to cast a Pin<&mut dyn DowncastTrait> to a pinned reference to an
+/// implemented trait, without unpinning the object e.g:
+/// ```ignore
+/// if let Some(animation) = downcast_trait_pin!(dyn Animation, node.as_mut())
+/// {
+///   //Use downcasted trait
+/// }
+/// ```
+///
+/// Only the object itself can be cast while it is pinned. Traits listed as
+/// `dyn Trait => self.field` and casts forwarded to a `delegate` field are not answered, since
+/// the field is not known to be structurally pinned.
+#[macro_export]
+macro_rules! downcast_trait_pin {
+    ($target:ty, $src:expr) => {
+        $crate::__private::cast_pin::<$target>($src)
+    };
+}
+
+/// This macro can be used to cast a `Pin<Box<dyn DowncastTrait>>` to a pinned box of an
+/// implemented trait, without moving the object. If the trait is not supported, the original box
+/// is returned as the error e.g:
+/// ```ignore
+/// match downcast_trait_pin_box!(dyn Future<Output = ()>, task)
+/// {
+///   Ok(future) => {} //Use downcasted trait
+///   Err(task) => {} //Try another trait
+/// }
+/// ```
+#[macro_export]
+macro_rules! downcast_trait_pin_box {
+    ($target:ty, $src:expr) => {
+        $crate::__private::cast_pin_box::<$target>($src)
+    };
+}
+
+/// This macro can be used to cast a `Rc<DowncastTrait>` to an Rc of an implemented trait. The
+/// result shares the allocation and reference count with the source. If the trait is not
+/// supported, the original Rc is returned as the error e.g:
+/// ```ignore
+/// if let Ok(sub_container) = downcast_trait_rc!(dyn Container, sub_widget.clone())
+/// {
+///   //Use downcasted trait
+/// }
+/// ```
+#[macro_export]
+macro_rules! downcast_trait_rc {
+    ($target:ty, $src:expr) => {
+        $crate::__private::cast_rc::<$target>($src)
+    };
+}
+
+/// This macro can be used to cast an `Arc<DowncastTrait>` to an Arc of an implemented trait. The
+/// result shares the allocation and reference count with the source. If the trait is not
+/// supported, the original Arc is returned as the error e.g:
+/// ```ignore
+/// if let Ok(job) = downcast_trait_arc!(dyn Job, task.clone())
+/// {
+///   //Use downcasted trait
+/// }
+/// ```
+#[macro_export]
+macro_rules! downcast_trait_arc {
+    ($target:ty, $src:expr) => {
+        $crate::__private::cast_arc::<$target>($src)
+    };
+}
+
+/// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_impl_convert_to_ref
+{
+    ($($target:ty),* ; $($maybe:ty),* ; $($projected:ty => [$($field:tt)+]),* ; $delegate:tt) => {
+        fn convert_to_trait<'s>(&'s self, slot: &mut $crate::TraitSlot<'s>) {
+            if slot.target() == $crate::__private::TypeId::of::<Self>()
+            {
+                slot.provide_ref::<Self>(self);
+                slot.provide_ptr::<Self, Self>(self, |this| this)
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$maybe>()
+            {
+                $crate::__private::provide_ref_if::<Self, $maybe>(self, slot)
+            }
+            )*
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$projected>()
+                && !slot.is_owned_probe()
+            {
+                slot.provide_ref::<$projected>(&self.$($field)+)
+            }
+            )*
+            else if $crate::__downcast_trait_use_table!($($target),*)
+            {
+                $crate::__downcast_trait_lookup!(
+                    slot.target(),
+                    for<'t> fn(&'t Self, &mut $crate::TraitSlot<'t>),
+                    (self, slot),
+                    $crate::__downcast_trait_miss!(ref self, slot, [$($target),*], $delegate),
+                    $($target => |this, slot| {
+                        slot.provide_ref::<$target>(this);
+                        slot.provide_ptr::<$target, Self>(this, |this| this)
+                    }),*
+                )
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$target>()
+            {
+                slot.provide_ref::<$target>(self);
+                slot.provide_ptr::<$target, Self>(self, |this| this)
+            }
+            )*
+            else
+            {
+                $crate::__downcast_trait_miss!(ref self, slot, [$($target),*], $delegate)
             }
         }
-        transmute_helper($src)
-    }};
+    }
 }
 
 /// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+#[doc(hidden)]
 #[macro_export]
-macro_rules! downcast_trait_impl_convert_to_ref
+macro_rules! __downcast_trait_impl_convert_to_mut
 {
-    ($(dyn $type:path),+) => {
-        unsafe fn convert_to_trait(& self, trait_id: TypeId) -> Option<& (dyn Any)> {
-            if false
+    ($($target:ty),* ; $($maybe:ty),* ; $($projected:ty => [$($field:tt)+]),* ; $delegate:tt) => {
+        fn convert_to_trait_mut<'s>(&'s mut self, slot: &mut $crate::TraitSlot<'s>) {
+            if slot.target() == $crate::__private::TypeId::of::<Self>()
             {
-               None
+                slot.provide_mut::<Self>(self)
             }
             $(
-            else if trait_id == TypeId::of::<dyn $type>()
+            else if slot.target() == $crate::__private::TypeId::of::<$maybe>()
             {
-                Some(mem::transmute::<& (dyn $type), & dyn Any>(
-                    self as & (dyn $type)
-                ))
+                $crate::__private::provide_mut_if::<Self, $maybe>(self, slot)
+            }
+            )*
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$projected>()
+            {
+                slot.provide_mut::<$projected>(&mut self.$($field)+)
+            }
+            )*
+            else if $crate::__downcast_trait_use_table!($($target),*)
+            {
+                $crate::__downcast_trait_lookup!(
+                    slot.target(),
+                    for<'t> fn(&'t mut Self, &mut $crate::TraitSlot<'t>),
+                    (self, slot),
+                    $crate::__downcast_trait_miss!(mut self, slot, [$($target),*], $delegate),
+                    $($target => |this, slot| slot.provide_mut::<$target>(this)),*
+                )
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$target>()
+            {
+                slot.provide_mut::<$target>(self)
             }
             )*
             else
             {
-                None
+                $crate::__downcast_trait_miss!(mut self, slot, [$($target),*], $delegate)
             }
         }
-        fn to_downcast_trait(& self) -> & dyn DowncastTrait
-        {
-            self
+        fn convert_to_trait_pin<'s>(self: $crate::__private::Pin<&'s mut Self>, slot: &mut $crate::TraitSlot<'s>) {
+            if slot.target() == $crate::__private::TypeId::of::<Self>()
+            {
+                slot.provide_pin::<Self>(self)
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$maybe>()
+            {
+                $crate::__private::provide_pin_if::<Self, $maybe>(self, slot)
+            }
+            )*
+            else if $crate::__downcast_trait_use_table!($($target),*)
+            {
+                $crate::__downcast_trait_lookup!(
+                    slot.target(),
+                    for<'t> fn($crate::__private::Pin<&'t mut Self>, &mut $crate::TraitSlot<'t>),
+                    (self, slot),
+                    $crate::__downcast_trait_miss!(pin self, slot, [$($target),*]),
+                    $($target => |this, slot| slot.provide_pin::<$target>(this)),*
+                )
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$target>()
+            {
+                slot.provide_pin::<$target>(self)
+            }
+            )*
+            else
+            {
+                $crate::__downcast_trait_miss!(pin self, slot, [$($target),*])
+            }
         }
     }
 }
 
 /// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+#[doc(hidden)]
 #[macro_export]
-macro_rules! downcast_trait_impl_convert_to_mut
+#[cfg(feature = "alloc")]
+macro_rules! __downcast_trait_impl_convert_to_box
 {
-    ($(dyn $type:path),+) => {
-        unsafe fn convert_to_trait_mut(& mut self, trait_id: TypeId) -> Option<& mut (dyn Any)> {
-            if false
+    ($($target:ty),* ; $($maybe:ty),* ; $($projected:ty => [$($field:tt)+]),* ; $delegate:tt) => {
+        fn convert_to_trait_box<'s>(self: $crate::__private::Box<Self>, slot: &mut $crate::TraitSlot<'s>)
+            -> $crate::__private::Result<$crate::Provided<'s>, $crate::__private::Box<dyn $crate::DowncastTrait>>
+        {
+            if slot.target() == $crate::__private::TypeId::of::<Self>()
+            {
+                $crate::__downcast_trait_provide!(provide_box slot, Self, self)
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$maybe>()
+            {
+                $crate::__private::provide_box_if::<Self, $maybe>(self, slot)
+            }
+            )*
+            else if $crate::__downcast_trait_use_table!($($target),*)
             {
-               None
+                $crate::__downcast_trait_lookup!(
+                    slot.target(),
+                    for<'t> fn($crate::__private::Box<Self>, &mut $crate::TraitSlot<'t>) -> $crate::__private::Result<$crate::Provided<'t>, $crate::__private::Box<dyn $crate::DowncastTrait>>,
+                    (self, slot),
+                    $crate::__downcast_trait_miss!(box self, slot, [$($target),*], $delegate),
+                    $($target => |this: $crate::__private::Box<Self>, slot: &mut $crate::TraitSlot<'_>| {
+                        $crate::__downcast_trait_provide!(provide_box slot, $target, this)
+                    }),*
+                )
             }
             $(
-            else if trait_id == TypeId::of::<dyn $type>()
+            else if slot.target() == $crate::__private::TypeId::of::<$target>()
             {
-                Some(mem::transmute::<& mut (dyn $type), & mut dyn Any>(
-                    self as & mut (dyn $type)
-                ))
+                $crate::__downcast_trait_provide!(provide_box slot, $target, self)
             }
             )*
             else
             {
-                None
+                $crate::__downcast_trait_miss!(box self, slot, [$($target),*], $delegate)
             }
         }
-        fn to_downcast_trait_mut(& mut self) -> & mut dyn DowncastTrait
+        fn convert_to_trait_pin_box<'s>(self: $crate::__private::Pin<$crate::__private::Box<Self>>, slot: &mut $crate::TraitSlot<'s>)
+            -> $crate::__private::Result<$crate::Provided<'s>, $crate::__private::Pin<$crate::__private::Box<dyn $crate::DowncastTrait>>>
         {
-            self
+            if slot.target() == $crate::__private::TypeId::of::<Self>()
+            {
+                $crate::__downcast_trait_provide!(provide_pin_box slot, Self, self)
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$maybe>()
+            {
+                $crate::__private::provide_pin_box_if::<Self, $maybe>(self, slot)
+            }
+            )*
+            else if $crate::__downcast_trait_use_table!($($target),*)
+            {
+                $crate::__downcast_trait_lookup!(
+                    slot.target(),
+                    for<'t> fn($crate::__private::Pin<$crate::__private::Box<Self>>, &mut $crate::TraitSlot<'t>) -> $crate::__private::Result<$crate::Provided<'t>, $crate::__private::Pin<$crate::__private::Box<dyn $crate::DowncastTrait>>>,
+                    (self, slot),
+                    $crate::__downcast_trait_miss!(provide_pin_box self, slot, [$($target),*]),
+                    $($target => |this: $crate::__private::Pin<$crate::__private::Box<Self>>, slot: &mut $crate::TraitSlot<'_>| {
+                        $crate::__downcast_trait_provide!(provide_pin_box slot, $target, this)
+                    }),*
+                )
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$target>()
+            {
+                $crate::__downcast_trait_provide!(provide_pin_box slot, $target, self)
+            }
+            )*
+            else
+            {
+                $crate::__downcast_trait_miss!(provide_pin_box self, slot, [$($target),*])
+            }
         }
     }
 }
 
 /// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+#[doc(hidden)]
 #[macro_export]
-#[cfg(feature = "std")]
-macro_rules! downcast_trait_impl_convert_to_box
+#[cfg(feature = "alloc")]
+macro_rules! __downcast_trait_impl_convert_to_rc
 {
-    ($(dyn $type:path),+) => {
-        unsafe fn convert_to_trait_box(self: Box<Self>, trait_id: TypeId) -> Option<Box<dyn Any>>{
-            if false{
-               None
+    ($($target:ty),* ; $($maybe:ty),* ; $($projected:ty => [$($field:tt)+]),* ; $delegate:tt) => {
+        fn convert_to_trait_rc<'s>(self: $crate::__private::Rc<Self>, slot: &mut $crate::TraitSlot<'s>)
+            -> $crate::__private::Result<$crate::Provided<'s>, $crate::__private::Rc<dyn $crate::DowncastTrait>>
+        {
+            if slot.target() == $crate::__private::TypeId::of::<Self>()
+            {
+                $crate::__downcast_trait_provide!(provide_rc slot, Self, self)
             }
             $(
-            else if trait_id == TypeId::of::<dyn $type>()
+            else if slot.target() == $crate::__private::TypeId::of::<$maybe>()
             {
-                Some(mem::transmute::<Box<dyn $type>, Box<dyn Any>>(
-                    self as Box<dyn $type>
-                ))
+                $crate::__private::provide_rc_if::<Self, $maybe>(self, slot)
+            }
+            )*
+            else if $crate::__downcast_trait_use_table!($($target),*)
+            {
+                $crate::__downcast_trait_lookup!(
+                    slot.target(),
+                    for<'t> fn($crate::__private::Rc<Self>, &mut $crate::TraitSlot<'t>) -> $crate::__private::Result<$crate::Provided<'t>, $crate::__private::Rc<dyn $crate::DowncastTrait>>,
+                    (self, slot),
+                    $crate::__downcast_trait_miss!(provide_rc self, slot, [$($target),*]),
+                    $($target => |this: $crate::__private::Rc<Self>, slot: &mut $crate::TraitSlot<'_>| {
+                        $crate::__downcast_trait_provide!(provide_rc slot, $target, this)
+                    }),*
+                )
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$target>()
+            {
+                $crate::__downcast_trait_provide!(provide_rc slot, $target, self)
             }
             )*
             else
             {
-                None
+                $crate::__downcast_trait_miss!(provide_rc self, slot, [$($target),*])
             }
         }
-        fn to_downcast_trait_box(self: Box<Self>) -> Box<dyn DowncastTrait>
+        #[cfg(target_has_atomic = "ptr")]
+        fn convert_to_trait_arc<'s>(self: $crate::__private::Arc<Self>, slot: &mut $crate::TraitSlot<'s>)
+            -> $crate::__private::Result<$crate::Provided<'s>, $crate::__private::Arc<dyn $crate::DowncastTrait>>
         {
-            self
+            if slot.target() == $crate::__private::TypeId::of::<Self>()
+            {
+                $crate::__downcast_trait_provide!(provide_arc slot, Self, self)
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$maybe>()
+            {
+                $crate::__private::provide_arc_if::<Self, $maybe>(self, slot)
+            }
+            )*
+            else if $crate::__downcast_trait_use_table!($($target),*)
+            {
+                $crate::__downcast_trait_lookup!(
+                    slot.target(),
+                    for<'t> fn($crate::__private::Arc<Self>, &mut $crate::TraitSlot<'t>) -> $crate::__private::Result<$crate::Provided<'t>, $crate::__private::Arc<dyn $crate::DowncastTrait>>,
+                    (self, slot),
+                    $crate::__downcast_trait_miss!(provide_arc self, slot, [$($target),*]),
+                    $($target => |this: $crate::__private::Arc<Self>, slot: &mut $crate::TraitSlot<'_>| {
+                        $crate::__downcast_trait_provide!(provide_arc slot, $target, this)
+                    }),*
+                )
+            }
+            $(
+            else if slot.target() == $crate::__private::TypeId::of::<$target>()
+            {
+                $crate::__downcast_trait_provide!(provide_arc slot, $target, self)
+            }
+            )*
+            else
+            {
+                $crate::__downcast_trait_miss!(provide_arc self, slot, [$($target),*])
+            }
         }
     }
 }
 
 /// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+/// to answer an owned cast with `$value` unsized to `$target`, handing `$value` back as the
+/// object if the slot does not accept it.
+#[doc(hidden)]
+#[macro_export]
+#[cfg(feature = "alloc")]
+macro_rules! __downcast_trait_provide
+{
+    ($provide:ident $slot:ident, $target:ty, $value:expr) => {
+        match $slot.$provide::<$target, _>($value, |value| value)
+        {
+            $crate::__private::Ok(provided) => $crate::__private::Ok(provided),
+            $crate::__private::Err(value) => $crate::__private::Err(value),
+        }
+    };
+}
+
+/// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+#[doc(hidden)]
+#[macro_export]
+#[cfg(not(feature = "alloc"))]
+macro_rules! __downcast_trait_impl_convert_to_rc
+{
+    ($($target:ty),* ; $($maybe:ty),* ; $($projected:ty => [$($field:tt)+]),* ; $delegate:tt) => {
+    }
+}
+
+/// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+#[doc(hidden)]
 #[macro_export]
-#[cfg(not(feature = "std"))]
-macro_rules! downcast_trait_impl_convert_to_box
+#[cfg(not(feature = "alloc"))]
+macro_rules! __downcast_trait_impl_convert_to_box
 {
-    ($(dyn $type:path),+) => {
+    ($($target:ty),* ; $($maybe:ty),* ; $($projected:ty => [$($field:tt)+]),* ; $delegate:tt) => {
     }
 }
 
@@ -256,19 +1083,230 @@ macro_rules! downcast_trait_impl_convert_to_box
 ///     downcast_trait_impl_convert_to!(dyn Container, dyn Scrollable, dyn Clickable);
 /// }
 /// ```
+///
+/// A trait can be listed with auto trait and lifetime bounds, e.g. `dyn Container + Send + Sync`.
+/// The object can then be cast both to the listed type and to the trait without its bounds
+/// (`dyn Container`), but not to other combinations of the bounds unless they are listed too.
+/// [supported_traits](trait.DowncastTrait.html#method.supported_traits) describes the entry only
+/// in the form it is listed in, so `dyn Container` has to be listed as well to be reported.
+///
+/// Decorators and newtypes can start the list with `delegate = self.field` to forward every cast
+/// that is not listed to the [DowncastTrait] implementation of the field, which may be a
+/// concrete type or a box holding a trait object with `DowncastTrait` as a supertrait:
+/// ```ignore
+/// impl<W: DowncastTrait + 'static> DowncastTrait for Bordered<W> {
+///     downcast_trait_impl_convert_to!(delegate = self.inner, dyn Framed);
+/// }
+/// ```
+/// A boxed cast answered by the field drops the rest of the wrapper and returns the field, while a
+/// boxed cast that neither supports returns the wrapper unchanged. `Rc`, `Arc` and pinned casts
+/// are not forwarded, and [supported_traits](trait.DowncastTrait.html#method.supported_traits) only
+/// lists the traits of the wrapper. The wrapper must not implement `Drop`, since the field is
+/// moved out of it.
+///
+/// A trait that is implemented by a field rather than the type itself can be listed as
+/// `dyn Scrollable => self.scroll_area`. Shared and mutable casts to it return the field, so
+/// `downcast_trait!(dyn Scrollable, window)` gives `&window.scroll_area as &dyn Scrollable`.
+/// Boxed, `Rc` and `Arc` casts can not return a field without the rest of the object, and are
+/// not supported for projected traits. Their descriptors report this with
+/// [supports_box](struct.TraitDescriptor.html#method.supports_box).
+///
+/// Pinned casts with [downcast_trait_pin](macro.downcast_trait_pin.html) and
+/// [downcast_trait_pin_box](macro.downcast_trait_pin_box.html) answer the listed traits, but
+/// neither projected traits nor the delegate field, since a field is not known to be structurally
+/// pinned.
+///
+/// With the `std` feature, lists of more than 16 traits are looked up in a hash table that each
+/// implementing type builds on its first cast. Without `std` the threshold is `usize::MAX`, and
+/// every list is checked one trait at a time.
 #[macro_export]
 macro_rules! downcast_trait_impl_convert_to
 {
-    ($(dyn $type:path),+) => {
-        downcast_trait_impl_convert_to_ref!($(dyn $type),*);
-        downcast_trait_impl_convert_to_mut!($(dyn $type),*);
-        downcast_trait_impl_convert_to_box!($(dyn $type),*);
-    }
+    (delegate = self $(. $field:tt)+ $(,)?) => {
+        $crate::__downcast_trait_impl_targets!([$($field).+] [] [] []);
+    };
+    (delegate = self $(. $field:tt)+ , $(dyn $type:path),+ $(,)?) => {
+        $crate::__downcast_trait_impl_targets!(
+            [$($field).+] [] [$({[dyn $type] [dyn $type]})+] [$({dyn $type})+]
+        );
+    };
+    (delegate = self $(. $field:tt)+ , $($tokens:tt)+) => {
+        $crate::__downcast_trait_split_targets!(@munch [$($field).+] [] [] [] [] [] [] $($tokens)+);
+    };
+    ($(dyn $type:path),+ $(,)?) => {
+        $crate::__downcast_trait_impl_targets!([] [] [$({[dyn $type] [dyn $type]})+] [$({dyn $type})+]);
+    };
+    ($($tokens:tt)+) => {
+        $crate::__downcast_trait_split_targets!(@munch [] [] [] [] [] [] [] $($tokens)+);
+    };
+}
+
+/// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+/// to split a list of targets with bounds such as `dyn Container<A, B> + Send` at the top level
+/// commas. The state is the delegate field, the targets projected to fields, the listed targets
+/// paired with their traits without bounds, the targets to answer, the depth of `<` in the
+/// current target, and the current target split into the trait and its `+` bounds.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_split_targets
+{
+    (@munch $delegate:tt $projected:tt [$($listed:tt)*] [$($answered:tt)*] [] [] []) => {
+        $crate::__downcast_trait_impl_targets!($delegate $projected [$($listed)*] [$($answered)*]);
+    };
+    // Targets without bounds are taken in one step, to stay within the recursion limit.
+    (@munch $delegate:tt $projected:tt [$($listed:tt)*] [$($answered:tt)*] [] [] [] $(dyn $type:path),+ $(,)?) => {
+        $crate::__downcast_trait_impl_targets!(
+            $delegate $projected
+            [$($listed)* $({[dyn $type] [dyn $type]})+]
+            [$($answered)* $({dyn $type})+]
+        );
+    };
+    (@munch $delegate:tt $projected:tt [$($listed:tt)*] [$($answered:tt)*] [] [] [] dyn $type:path, $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(
+            @munch $delegate $projected
+            [$($listed)* {[dyn $type] [dyn $type]}]
+            [$($answered)* {dyn $type}]
+            [] [] [] $($rest)*
+        );
+    };
+    (@munch $delegate:tt $projected:tt $listed:tt $answered:tt [] $trait:tt $bounds:tt , $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(@push $delegate $projected $listed $answered $trait $bounds $($rest)*);
+    };
+    (@munch $delegate:tt [$($projected:tt)*] $listed:tt $answered:tt [] [$($trait:tt)+] [] => self $(. $field:tt)+ $(, $($rest:tt)*)?) => {
+        $crate::__downcast_trait_split_targets!(
+            @munch $delegate
+            [$($projected)* {[$($trait)+] [$($trait)+] [$($field).+] [{$($trait)+}]}]
+            $listed $answered [] [] [] $($($rest)*)?
+        );
+    };
+    (@munch $delegate:tt [$($projected:tt)*] $listed:tt $answered:tt [] [$($trait:tt)+] [$($bounds:tt)+] => self $(. $field:tt)+ $(, $($rest:tt)*)?) => {
+        $crate::__downcast_trait_split_targets!(
+            @munch $delegate
+            [$($projected)* {
+                [$($trait)+ $($bounds)+] [$($trait)+] [$($field).+] [{$($trait)+ $($bounds)+} {$($trait)+}]
+            }]
+            $listed $answered [] [] [] $($($rest)*)?
+        );
+    };
+    (@munch $delegate:tt $projected:tt $listed:tt $answered:tt [] [$($trait:tt)+] $bounds:tt) => {
+        $crate::__downcast_trait_split_targets!(@push $delegate $projected $listed $answered [$($trait)+] $bounds);
+    };
+    (@munch $delegate:tt $projected:tt $listed:tt $answered:tt [] [$($trait:tt)+] [$($bounds:tt)*] + $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(@munch $delegate $projected $listed $answered [] [$($trait)+] [$($bounds)* +] $($rest)*);
+    };
+    (@munch $delegate:tt $projected:tt $listed:tt $answered:tt $depth:tt $trait:tt [$($bounds:tt)+] $next:tt $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(@munch $delegate $projected $listed $answered $depth $trait [$($bounds)+ $next] $($rest)*);
+    };
+    (@munch $delegate:tt $projected:tt $listed:tt $answered:tt [$($depth:tt)*] [$($trait:tt)*] [] < $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(@munch $delegate $projected $listed $answered [$($depth)* <] [$($trait)* <] [] $($rest)*);
+    };
+    (@munch $delegate:tt $projected:tt $listed:tt $answered:tt [< $($depth:tt)*] [$($trait:tt)*] [] > $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(@munch $delegate $projected $listed $answered [$($depth)*] [$($trait)* >] [] $($rest)*);
+    };
+    (@munch $delegate:tt $projected:tt $listed:tt $answered:tt [< < $($depth:tt)*] [$($trait:tt)*] [] >> $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(@munch $delegate $projected $listed $answered [$($depth)*] [$($trait)* >>] [] $($rest)*);
+    };
+    (@munch $delegate:tt $projected:tt $listed:tt $answered:tt $depth:tt [$($trait:tt)*] [] $next:tt $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(@munch $delegate $projected $listed $answered $depth [$($trait)* $next] [] $($rest)*);
+    };
+    (@push $delegate:tt $projected:tt [$($listed:tt)*] [$($answered:tt)*] [$($trait:tt)+] [] $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(
+            @munch $delegate $projected
+            [$($listed)* {[$($trait)+] [$($trait)+]}]
+            [$($answered)* {$($trait)+}]
+            [] [] [] $($rest)*
+        );
+    };
+    (@push $delegate:tt $projected:tt [$($listed:tt)*] [$($answered:tt)*] [$($trait:tt)+] [$($bounds:tt)+] $($rest:tt)*) => {
+        $crate::__downcast_trait_split_targets!(
+            @munch $delegate $projected
+            [$($listed)* {[$($trait)+ $($bounds)+] [$($trait)+]}]
+            [$($answered)* {$($trait)+ $($bounds)+} {$($trait)+}]
+            [] [] [] $($rest)*
+        );
+    };
+}
+
+/// This macro is used internally by the borrowed macros and
+/// [assert_cast_list_complete](macro.assert_cast_list_complete.html) to split a list of trait
+/// objects at the commas that are not inside generic arguments, and pass them to `$callback` after
+/// `$args`, as `{dyn Expr<'a> + Send}`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_split_list {
+    (@munch $callback:ident $args:tt [$($targets:tt)*] [] [$($target:tt)+] $(,)?) => {
+        $crate::$callback!($args $($targets)* {$($target)+});
+    };
+    (@munch $callback:ident $args:tt [$($targets:tt)*] [] [$($target:tt)+] , $($rest:tt)+) => {
+        $crate::__downcast_trait_split_list!(
+            @munch $callback $args [$($targets)* {$($target)+}] [] [] $($rest)+
+        );
+    };
+    (@munch $callback:ident $args:tt $targets:tt [$($depth:tt)*] [$($target:tt)*] < $($rest:tt)+) => {
+        $crate::__downcast_trait_split_list!(
+            @munch $callback $args $targets [$($depth)* <] [$($target)* <] $($rest)+
+        );
+    };
+    (@munch $callback:ident $args:tt $targets:tt [< $($depth:tt)*] [$($target:tt)*] > $($rest:tt)*) => {
+        $crate::__downcast_trait_split_list!(
+            @munch $callback $args $targets [$($depth)*] [$($target)* >] $($rest)*
+        );
+    };
+    (@munch $callback:ident $args:tt $targets:tt [< < $($depth:tt)*] [$($target:tt)*] >> $($rest:tt)*) => {
+        $crate::__downcast_trait_split_list!(
+            @munch $callback $args $targets [$($depth)*] [$($target)* >>] $($rest)*
+        );
+    };
+    (@munch $callback:ident $args:tt $targets:tt $depth:tt [$($target:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__downcast_trait_split_list!(
+            @munch $callback $args $targets $depth [$($target)* $next] $($rest)*
+        );
+    };
+}
+
+/// This macro is used internally by [downcast_trait_impl_convert_to](macro.downcast_trait_impl_convert_to.html)
+/// to implement the trait for the listed targets, answering casts to every target in `answered`,
+/// casts to the `projected` targets with their fields, and forwarding other casts to the
+/// `delegate` field if one is given.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_impl_targets
+{
+    (
+        $delegate:tt
+        [$({[$($plisted:tt)+] [$($ptrait:tt)+] $field:tt [$({$($panswered:tt)+})+]})*]
+        [$({[$($listed:tt)+] [$($ltrait:tt)+]})*]
+        [$({$($answered:tt)+})*]
+    ) => {
+        $crate::__downcast_trait_impl_convert_to_ref!(
+            $($($answered)+),* ; ; $($($($panswered)+ => $field),+),* ; $delegate
+        );
+        $crate::__downcast_trait_impl_convert_to_mut!(
+            $($($answered)+),* ; ; $($($($panswered)+ => $field),+),* ; $delegate
+        );
+        $crate::__downcast_trait_impl_convert_to_box!($($($answered)+),* ; ; ; $delegate);
+        $crate::__downcast_trait_impl_convert_to_rc!($($($answered)+),* ; ; ; []);
+        $crate::__downcast_trait_capability_mask!(
+            [$($($answered)+),*] [$($($($panswered)+),+),*] $delegate
+        );
+        fn listed_traits() -> &'static [$crate::TraitDescriptor] {
+            const {
+                &[
+                    $($crate::TraitDescriptor::entry::<$($listed)+, $($ltrait)+>(false),)*
+                    $($crate::TraitDescriptor::entry::<$($plisted)+, $($ptrait)+>(true),)*
+                ]
+            }
+        }
+    };
 }
 
 #[cfg(test)]
 mod tests {
+    extern crate std;
     use super::*;
+    #[cfg(feature = "alloc")]
+    use std::boxed::Box;
+    use std::{format, vec, vec::Vec};
     trait Downcasted {
         fn get_number(&self) -> u32;
     }
@@ -310,17 +1348,654 @@ mod tests {
             Some(downcasted_mut) => {
                 assert_eq!(downcasted_mut.get_number(), 456);
             }
-            None => assert!(false),
+            None => panic!("cast should succeed"),
         }
 
-        let tst2 = Box::new(Downcastable { val: 0 });
-        let downcasted_maybebox = downcast_trait_box!(dyn Downcasted2, tst2);
-        match downcasted_maybebox {
-            Some(downcasted_mut) => {
-                assert_eq!(downcasted_mut.get_number(), 456);
+        #[cfg(feature = "alloc")]
+        {
+            let tst2 = Box::new(Downcastable { val: 0 });
+            let downcasted_maybebox = downcast_trait_box!(dyn Downcasted2, tst2);
+            match downcasted_maybebox {
+                Ok(downcasted_mut) => {
+                    assert_eq!(downcasted_mut.get_number(), 456);
+                }
+                Err(_) => panic!("cast should succeed"),
             }
-            None => assert!(false),
         }
+    }
+
+    #[test]
+    fn ref_round_trip() {
+        let tst = Downcastable { val: 1 };
+        let ts: &dyn DowncastTrait = tst.to_downcast_trait();
+        let downcasted = downcast_trait!(dyn Downcasted, ts).expect("cast should succeed");
+        assert_eq!(downcasted.get_number(), 124);
+        assert!(core::ptr::eq(
+            downcasted as *const dyn Downcasted as *const u8,
+            &tst as *const Downcastable as *const u8
+        ));
+        assert!(downcast_trait!(dyn core::fmt::Debug, ts).is_none());
+    }
 
+    #[test]
+    fn mut_round_trip() {
+        let mut tst = Downcastable { val: 1 };
+        let ts: &mut dyn DowncastTrait = tst.to_downcast_trait_mut();
+        assert!(downcast_trait_mut!(dyn core::fmt::Debug, ts).is_none());
+        let downcasted = downcast_trait_mut!(dyn Downcasted2, ts).expect("cast should succeed");
+        assert_eq!(downcasted.get_number(), 457);
+        assert_eq!(tst.val, 1);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn box_round_trip() {
+        let tst: Box<dyn DowncastTrait> = Box::new(Downcastable { val: 2 });
+        let Ok(downcasted) = downcast_trait_box!(dyn Downcasted, tst) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(downcasted.get_number(), 125);
+
+        let tst: Box<dyn DowncastTrait> = Box::new(Downcastable { val: 2 });
+        let Err(tst) = downcast_trait_box!(dyn core::fmt::Debug, tst) else {
+            panic!("cast should fail")
+        };
+        let Ok(downcasted) = downcast_trait_box!(dyn Downcasted2, tst) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(downcasted.get_number(), 458);
+    }
+
+    #[test]
+    fn ptr_round_trip() {
+        let mut tst = Downcastable { val: 5 };
+        let ts: NonNull<dyn DowncastTrait> = NonNull::from(&mut tst);
+        assert!(unsafe { cast_ptr::<dyn core::fmt::Debug>(ts) }.is_none());
+        let downcasted = unsafe { cast_ptr::<dyn Downcasted>(ts) }.expect("cast should succeed");
+        assert_eq!(downcasted.as_ptr() as *mut u8, ts.as_ptr() as *mut u8);
+        assert_eq!(unsafe { downcasted.as_ref() }.get_number(), 128);
+        let mut downcasted2 = unsafe { cast_ptr::<dyn Downcasted2>(ts) }.unwrap();
+        assert_eq!(unsafe { downcasted2.as_mut() }.get_number(), 461);
+    }
+
+    #[test]
+    fn ptr_interleaved_with_src() {
+        // The result is derived from src rather than from the reference the cast is dispatched
+        // through, so the two can be used in turns. Run with Miri to check the provenance.
+        let mut tst = Downcastable { val: 5 };
+        let ts: NonNull<dyn DowncastTrait> = NonNull::from(&mut tst);
+        let downcasted = unsafe { cast_ptr::<dyn Downcasted2>(ts) }.unwrap();
+        assert_eq!(unsafe { downcasted.as_ref() }.get_number(), 461);
+        let object = unsafe { &mut *ts.as_ptr() };
+        object.downcast_mut::<Downcastable>().unwrap().val = 6;
+        assert_eq!(unsafe { downcasted.as_ref() }.get_number(), 462);
+        let again = unsafe { cast_ptr::<dyn Downcasted>(ts) }.unwrap();
+        unsafe { &mut *ts.as_ptr() }.downcast_mut::<Downcastable>().unwrap().val = 7;
+        assert_eq!(unsafe { again.as_ref() }.get_number(), 130);
+        assert_eq!(unsafe { downcasted.as_ref() }.get_number(), 463);
+        assert_eq!(tst.val, 7);
+    }
+
+    struct Pinned {
+        val: u32,
+        _pin: core::marker::PhantomPinned,
+    }
+    impl Downcasted for Pinned {
+        fn get_number(&self) -> u32 {
+            self.val
+        }
+    }
+    impl DowncastTrait for Pinned {
+        downcast_trait_impl_convert_to!(dyn Downcasted);
+    }
+
+    #[test]
+    fn pin_round_trip() {
+        let mut tst = core::pin::pin!(Pinned {
+            val: 3,
+            _pin: core::marker::PhantomPinned,
+        });
+        let address = &*tst as *const Pinned as *const u8;
+        let mut ts: Pin<&mut dyn DowncastTrait> = tst.as_mut();
+        let downcasted =
+            downcast_trait_pin!(dyn Downcasted, ts.as_mut()).expect("cast should succeed");
+        assert_eq!(downcasted.get_number(), 3);
+        assert!(core::ptr::eq(&*downcasted as *const dyn Downcasted as *const u8, address));
+        assert!(downcast_trait_pin!(dyn Downcasted2, ts.as_mut()).is_none());
+        assert!(ts.as_mut().cast_pin::<dyn core::fmt::Debug>().is_none());
+        assert!(downcast_trait_pin!(Pinned, ts.as_mut()).is_some());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn pin_box_round_trip() {
+        let tst: Pin<Box<dyn DowncastTrait>> = Box::pin(Pinned {
+            val: 4,
+            _pin: core::marker::PhantomPinned,
+        });
+        let address = &*tst as *const dyn DowncastTrait as *const u8;
+        let Err(tst) = downcast_trait_pin_box!(dyn core::fmt::Debug, tst) else {
+            panic!("cast should fail")
+        };
+        let Err(tst) = tst.cast_pin_box::<dyn core::fmt::Display>() else {
+            panic!("cast should fail")
+        };
+        let Ok(downcasted) = downcast_trait_pin_box!(dyn Downcasted, tst) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(downcasted.get_number(), 4);
+        assert!(core::ptr::eq(&*downcasted as *const dyn Downcasted as *const u8, address));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn cast_chain() {
+        let tst: Box<dyn DowncastTrait> = Box::new(Downcastable { val: 4 });
+        let number = CastChain::new(tst)
+            .or_cast(|_: Box<dyn core::fmt::Debug>| 0)
+            .or_cast(|d: Box<dyn Downcasted2>| d.get_number())
+            .or_cast(|d: Box<dyn Downcasted>| d.get_number())
+            .finish();
+        assert_eq!(number.ok(), Some(460));
+
+        let tst: Box<dyn DowncastTrait> = Box::new(Downcastable { val: 4 });
+        let Err(unmatched) = CastChain::new(tst)
+            .or_cast(|_: Box<dyn core::fmt::Display>| ())
+            .finish()
+        else {
+            panic!("no cast should succeed")
+        };
+        assert_eq!(unmatched.cast_ref::<dyn Downcasted>().map(|d| d.get_number()), Some(127));
+    }
+
+    impl CastTarget for dyn Downcasted {}
+    impl CastTarget for dyn Downcasted2 {}
+
+    #[cfg(feature = "alloc")]
+    fn sum_numbers<T: ?Sized + CastTarget>(
+        items: &[Box<dyn DowncastTrait>],
+        number: impl Fn(&T) -> u32,
+    ) -> u32 {
+        items
+            .iter()
+            .filter_map(|item| item.cast_ref::<T>())
+            .map(number)
+            .sum()
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn cast_methods() {
+        let items: Vec<Box<dyn DowncastTrait>> = vec![
+            Box::new(Downcastable { val: 1 }),
+            Box::new(Downcastable { val: 2 }),
+        ];
+        assert_eq!(sum_numbers::<dyn Downcasted>(&items, |d| d.get_number()), 249);
+        assert_eq!(sum_numbers::<dyn Downcasted2>(&items, |d| d.get_number()), 915);
+        assert_eq!(sum_numbers::<dyn core::fmt::Debug>(&items, |_| 1), 0);
+
+        let mut tst = Downcastable { val: 3 };
+        let ts = tst.to_downcast_trait_mut();
+        assert_eq!(ts.cast_mut::<dyn Downcasted>().map(|d| d.get_number()), Some(126));
+        let boxed = items.into_iter().next().unwrap();
+        assert_eq!(boxed.cast_box::<dyn Downcasted2>().map(|d| d.get_number()).ok(), Some(457));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn rc_round_trip() {
+        let tst: Rc<dyn DowncastTrait> = Rc::new(Downcastable { val: 5 });
+        let weak_src = Rc::downgrade(&tst);
+        let Err(tst) = downcast_trait_rc!(dyn core::fmt::Debug, tst) else {
+            panic!("cast should fail")
+        };
+        let Ok(downcasted) = downcast_trait_rc!(dyn Downcasted, tst.clone()) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(downcasted.get_number(), 128);
+        assert_eq!(Rc::strong_count(&tst), 2);
+        assert!(core::ptr::eq(
+            Rc::as_ptr(&downcasted) as *const u8,
+            Rc::as_ptr(&tst) as *const u8
+        ));
+
+        let Ok(weak) = cast_rc_weak::<dyn Downcasted2>(weak_src.clone()) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(weak.upgrade().map(|d| d.get_number()), Some(461));
+        assert_eq!(Rc::weak_count(&tst), 2);
+
+        let Err(tst) = tst.rc_into_downcast_trait_box() else {
+            panic!("object is shared")
+        };
+        drop(downcasted);
+        let Ok(boxed) = tst.rc_into_downcast_trait_box() else {
+            panic!("object is unique")
+        };
+        assert!(weak.upgrade().is_none());
+        assert!(cast_rc_weak::<dyn Downcasted2>(weak_src).is_err());
+        assert_eq!(boxed.cast_ref::<dyn Downcasted>().map(|d| d.get_number()), Some(128));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn arc_round_trip() {
+        let tst: Arc<dyn DowncastTrait> = Arc::new(Downcastable { val: 6 });
+        let Ok(downcasted) = tst.clone().cast_arc::<dyn Downcasted2>() else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(downcasted.get_number(), 462);
+        assert_eq!(Arc::strong_count(&tst), 2);
+
+        let Ok(weak) = cast_arc_weak::<dyn Downcasted>(Arc::downgrade(&tst)) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(weak.upgrade().map(|d| d.get_number()), Some(129));
+
+        let Err(tst) = downcast_trait_arc!(dyn core::fmt::Display, tst) else {
+            panic!("cast should fail")
+        };
+        drop(downcasted);
+        let Ok(boxed) = tst.arc_into_downcast_trait_box() else {
+            panic!("object is unique")
+        };
+        assert!(weak.upgrade().is_none());
+        assert_eq!(boxed.cast_ref::<dyn Downcasted2>().map(|d| d.get_number()), Some(462));
+    }
+
+    struct Other;
+    impl DowncastTrait for Other {
+        downcast_trait_impl_convert_to!(dyn Downcasted);
+    }
+    impl Downcasted for Other {
+        fn get_number(&self) -> u32 {
+            0
+        }
+    }
+
+    #[test]
+    fn concrete_downcast() {
+        let mut tst = Downcastable { val: 7 };
+        let ts: &mut dyn DowncastTrait = tst.to_downcast_trait_mut();
+        assert!(ts.is::<Downcastable>());
+        assert!(!ts.is::<Other>());
+        assert_eq!(DowncastTrait::type_id(ts), TypeId::of::<Downcastable>());
+        assert!(ts.type_name().ends_with("Downcastable"));
+        assert!(ts.downcast_ref::<Other>().is_none());
+        ts.downcast_mut::<Downcastable>().expect("cast should succeed").val = 8;
+        assert_eq!(ts.downcast_ref::<Downcastable>().map(|d| d.val), Some(8));
+
+        #[cfg(feature = "alloc")]
+        {
+            let items: Vec<Box<dyn DowncastTrait>> = vec![Box::new(Other), Box::new(tst)];
+            let mut items = items.into_iter();
+            let Err(other) = items.next().unwrap().downcast_box::<Downcastable>() else {
+                panic!("cast should fail")
+            };
+            assert!(other.downcast_box::<Other>().is_ok());
+            let Ok(tst) = items.next().unwrap().downcast_box::<Downcastable>() else {
+                panic!("cast should succeed")
+            };
+            assert_eq!(tst.val, 8);
+
+            let shared: Rc<dyn DowncastTrait> = Rc::new(Other);
+            assert!(shared.downcast_rc::<Other>().is_ok());
+        }
+    }
+
+    #[test]
+    fn supported_traits() {
+        let tst = Downcastable { val: 0 };
+        let traits = tst.supported_traits();
+        assert_eq!(traits.len(), 2);
+        assert_eq!(traits[0].type_id(), TypeId::of::<dyn Downcasted>());
+        assert_eq!(traits[1].type_id(), TypeId::of::<dyn Downcasted2>());
+        assert!(traits[1].type_name().ends_with("Downcasted2"));
+        assert!(traits.iter().all(|t| t.supports_ref() && t.supports_mut()));
+        assert!(traits
+            .iter()
+            .all(|t| t.supports_box() == cfg!(feature = "alloc")));
+
+        let debug = format!("{:?}", tst.to_downcast_trait());
+        assert!(debug.contains("Downcastable"));
+        assert!(debug.contains("Downcasted2"));
+    }
+
+    macro_rules! numbered_traits {
+        ($($name:ident = $number:expr),+) => {
+            $(
+            #[allow(dead_code)]
+            trait $name {
+                fn number(&self) -> u32;
+            }
+            impl<T> $name for Many<T> {
+                fn number(&self) -> u32 {
+                    $number
+                }
+            }
+            )+
+        };
+    }
+    struct Many<T>(core::marker::PhantomData<T>);
+    numbered_traits!(
+        N0 = 0, N1 = 1, N2 = 2, N3 = 3, N4 = 4, N5 = 5, N6 = 6, N7 = 7, N8 = 8,
+        N9 = 9, N10 = 10, N11 = 11, N12 = 12, N13 = 13, N14 = 14, N15 = 15, N16 = 16
+    );
+    trait Holds<T> {
+        fn held(&self) -> &'static str;
+    }
+    impl<T> Holds<T> for Many<T> {
+        fn held(&self) -> &'static str {
+            core::any::type_name::<T>()
+        }
+    }
+    impl<T: 'static> DowncastTrait for Many<T> {
+        downcast_trait_impl_convert_to!(
+            dyn N0, dyn N1, dyn N2, dyn N3, dyn N4, dyn N5, dyn N6, dyn N7, dyn N8,
+            dyn N9, dyn N10, dyn N11, dyn N12, dyn N13, dyn N14, dyn N15, dyn N16, dyn Holds<T>
+        );
+    }
+
+    #[test]
+    fn table_lookup() {
+        // The table is only cached with std, and long lists use the chain otherwise.
+        assert_eq!(
+            __downcast_trait_use_table!(
+                dyn N0, dyn N1, dyn N2, dyn N3, dyn N4, dyn N5, dyn N6, dyn N7, dyn N8,
+                dyn N9, dyn N10, dyn N11, dyn N12, dyn N13, dyn N14, dyn N15, dyn N16, dyn Holds<u8>
+            ),
+            cfg!(feature = "std")
+        );
+        let mut many = Many::<u8>(core::marker::PhantomData);
+        let ts = many.to_downcast_trait_mut();
+        assert_eq!(downcast_trait!(dyn N0, ts).map(|n| n.number()), Some(0));
+        assert_eq!(downcast_trait!(dyn N5, ts).map(|n| n.number()), Some(5));
+        assert_eq!(downcast_trait_mut!(dyn N16, ts).map(|n| n.number()), Some(16));
+        assert!(downcast_trait!(dyn Downcasted, ts).is_none());
+        assert!(ts.downcast_ref::<Many<u8>>().is_some());
+
+        // Each instantiation builds its own table for the generic implementation.
+        assert_eq!(downcast_trait!(dyn Holds<u8>, ts).map(|h| h.held()), Some("u8"));
+        let other = Many::<u16>(core::marker::PhantomData);
+        let other = other.to_downcast_trait();
+        assert_eq!(downcast_trait!(dyn Holds<u16>, other).map(|h| h.held()), Some("u16"));
+        assert_eq!(downcast_trait!(dyn N3, other).map(|n| n.number()), Some(3));
+        assert!(downcast_trait!(dyn Holds<u8>, other).is_none());
+
+        #[cfg(feature = "alloc")]
+        {
+            let boxed: Box<dyn DowncastTrait> = Box::new(Many::<u8>(core::marker::PhantomData));
+            let Err(boxed) = downcast_trait_box!(dyn Downcasted, boxed) else {
+                panic!("cast should fail")
+            };
+            let Ok(n7) = downcast_trait_box!(dyn N7, boxed) else {
+                panic!("cast should succeed")
+            };
+            assert_eq!(n7.number(), 7);
+            let shared: Rc<dyn DowncastTrait> = Rc::new(Many::<u8>(core::marker::PhantomData));
+            assert!(downcast_trait_rc!(dyn N1, shared).is_ok());
+        }
+    }
+
+    trait Pair<A, B> {
+        fn pair(&self) -> (A, B);
+    }
+    struct Bounded;
+    impl Downcasted for Bounded {
+        fn get_number(&self) -> u32 {
+            9
+        }
+    }
+    impl Downcasted2 for Bounded {
+        fn get_number(&self) -> u32 {
+            10
+        }
+    }
+    impl Pair<u8, Vec<Vec<u8>>> for Bounded {
+        fn pair(&self) -> (u8, Vec<Vec<u8>>) {
+            (11, vec![vec![12]])
+        }
+    }
+    impl DowncastTrait for Bounded {
+        downcast_trait_impl_convert_to!(
+            dyn Downcasted + Send + Sync,
+            dyn Downcasted2 + 'static,
+            dyn Pair<u8, Vec<Vec<u8>>> + Send,
+        );
+    }
+
+    #[test]
+    fn bounded_targets() {
+        let ts = Bounded.to_downcast_trait();
+        let shared = downcast_trait!(dyn Downcasted + Send + Sync, ts).expect("cast should succeed");
+        assert_eq!(shared.get_number(), 9);
+        assert!(downcast_trait!(dyn Downcasted, ts).is_some());
+        assert!(downcast_trait!(dyn Downcasted + Send, ts).is_none());
+        assert!(downcast_trait!(dyn Downcasted2, ts).is_some());
+        assert_eq!(
+            downcast_trait!(dyn Pair<u8, Vec<Vec<u8>>> + Send, ts).map(|p| p.pair().0),
+            Some(11)
+        );
+        assert!(downcast_trait!(dyn Pair<u8, Vec<Vec<u8>>>, ts).is_some());
+
+        let traits = ts.supported_traits();
+        assert_eq!(traits.len(), 3);
+        assert_eq!(traits[0].type_id(), TypeId::of::<dyn Downcasted + Send + Sync>());
+        // The bounded entry answers dyn Downcasted, but is only described as listed.
+        assert!(traits
+            .iter()
+            .all(|t| t.type_id() != TypeId::of::<dyn Downcasted>()));
+
+        #[cfg(feature = "alloc")]
+        {
+            let boxed: Box<dyn DowncastTrait> = Box::new(Bounded);
+            let Ok(sendable) = downcast_trait_box!(dyn Downcasted + Send + Sync, boxed) else {
+                panic!("cast should succeed")
+            };
+            let number = std::thread::spawn(move || sendable.get_number()).join();
+            assert_eq!(number.ok(), Some(9));
+            let shared: Arc<dyn DowncastTrait> = Arc::new(Bounded);
+            assert!(downcast_trait_arc!(dyn Downcasted + Send + Sync, shared).is_ok());
+        }
+    }
+
+    struct Area {
+        offset: u32,
+    }
+    impl Downcasted for Area {
+        fn get_number(&self) -> u32 {
+            self.offset
+        }
+    }
+    struct Parts {
+        scroll: Area,
+        nested: (Area, Area),
+    }
+    impl Downcasted2 for Parts {
+        fn get_number(&self) -> u32 {
+            20
+        }
+    }
+    impl DowncastTrait for Parts {
+        downcast_trait_impl_convert_to!(
+            dyn Downcasted + Send => self.scroll,
+            dyn Downcasted2,
+            dyn Pair<u8, u8> => self.nested.1,
+        );
+    }
+    impl Pair<u8, u8> for Area {
+        fn pair(&self) -> (u8, u8) {
+            (self.offset as u8, 0)
+        }
+    }
+
+    #[test]
+    fn projected_targets() {
+        let mut parts = Parts {
+            scroll: Area { offset: 3 },
+            nested: (Area { offset: 4 }, Area { offset: 5 }),
+        };
+        let ts = parts.to_downcast_trait_mut();
+        assert_eq!(downcast_trait!(dyn Downcasted, ts).unwrap().get_number(), 3);
+        assert!(downcast_trait!(dyn Downcasted + Send, ts).is_some());
+        assert_eq!(
+            downcast_trait!(dyn Downcasted2, ts).unwrap().get_number(),
+            20
+        );
+        assert_eq!(downcast_trait!(dyn Pair<u8, u8>, ts).unwrap().pair().0, 5);
+        let projected = downcast_trait_mut!(dyn Downcasted, ts).unwrap() as *mut dyn Downcasted;
+        assert_eq!(
+            projected as *mut u8,
+            &mut parts.scroll as *mut Area as *mut u8
+        );
+
+        // Fields are not structurally pinned, so projected traits can not be cast while pinned.
+        let mut pinned = Pin::new(&mut parts);
+        assert!(downcast_trait_pin!(dyn Downcasted, pinned.as_mut()).is_none());
+        assert!(downcast_trait_pin!(dyn Downcasted2, pinned).is_some());
+
+        let traits = parts.supported_traits();
+        assert_eq!(traits.len(), 3);
+        assert_eq!(traits[0].supports_box(), cfg!(feature = "alloc"));
+        assert!(!traits[1].supports_box());
+        assert_eq!(traits[1].type_id(), TypeId::of::<dyn Downcasted + Send>());
+
+        // Projected traits can not be cast on the box path.
+        #[cfg(feature = "alloc")]
+        {
+            let boxed: Box<dyn DowncastTrait> = Box::new(parts);
+            assert!(downcast_trait_box!(dyn Downcasted, boxed).is_err());
+        }
+    }
+
+    // Code generated by the macros, and casts answered by hand, need no unsafe code.
+    #[forbid(unsafe_code)]
+    mod safe {
+        use super::{Area, Downcastable, Downcasted};
+        #[cfg(feature = "alloc")]
+        use super::{Box, Downcasted2};
+        #[cfg(feature = "alloc")]
+        use crate::Provided;
+        use crate::{downcast_trait_hierarchy, DowncastTrait, TraitSlot};
+        use core::any::TypeId;
+
+        pub trait Framed: Downcasted {}
+        downcast_trait_hierarchy!(dyn Framed: dyn Downcasted);
+        impl Framed for Area {}
+
+        pub struct Frame {
+            pub area: Area,
+            pub inner: Downcastable,
+        }
+        impl DowncastTrait for Frame {
+            downcast_trait_impl_convert_to!(
+                delegate = self.inner,
+                dyn Framed => self.area,
+            );
+        }
+
+        /// Answers shared casts to `Downcasted`, but not mutable ones, and answers boxed casts
+        /// to it with a box of the wrong trait object type.
+        pub struct Mislabeled(pub Downcastable);
+        impl DowncastTrait for Mislabeled {
+            fn convert_to_trait<'s>(&'s self, slot: &mut TraitSlot<'s>) {
+                if slot.target() == TypeId::of::<dyn Downcasted>() {
+                    slot.provide_ref::<dyn Downcasted>(&self.0);
+                }
+            }
+            fn convert_to_trait_mut<'s>(&'s mut self, _slot: &mut TraitSlot<'s>) {}
+            #[cfg(feature = "alloc")]
+            fn convert_to_trait_box<'s>(
+                self: Box<Self>,
+                slot: &mut TraitSlot<'s>,
+            ) -> Result<Provided<'s>, Box<dyn DowncastTrait>> {
+                if slot.target() != TypeId::of::<dyn Downcasted>() {
+                    return Err(self);
+                }
+                match slot.provide_box::<dyn Downcasted2, _>(self, |this| Box::new(this.0)) {
+                    Ok(provided) => Ok(provided),
+                    Err(this) => Err(this),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn safe_implementations() {
+        let mut frame = safe::Frame {
+            area: Area { offset: 1 },
+            inner: Downcastable { val: 2 },
+        };
+        let ts = frame.to_downcast_trait_mut();
+        assert!(downcast_trait!(dyn safe::Framed, ts).is_some());
+        assert_eq!(downcast_trait_mut!(dyn Downcasted, ts).unwrap().get_number(), 125);
+        assert!(ts.downcast_ref::<safe::Frame>().is_some());
+        let mut pinned = Pin::new(&mut frame);
+        assert!(downcast_trait_pin!(dyn Downcasted, pinned.as_mut()).is_none());
+        assert!(downcast_trait_pin!(safe::Frame, pinned).is_some());
+    }
+
+    #[test]
+    fn disagreeing_answers() {
+        // A mutable cast is tried once, and its failure leaves the source usable for later arms.
+        let mut object = safe::Mislabeled(Downcastable { val: 0 });
+        let matched = match_trait!(object.to_downcast_trait_mut() {
+            dyn Downcasted as _ => "mutable",
+            _ => "fallback",
+        });
+        assert_eq!(matched, "fallback");
+        let matched = match_trait!(object.to_downcast_trait() {
+            dyn Downcasted as _ => "shared",
+            _ => "fallback",
+        });
+        assert_eq!(matched, "shared");
+        // It answers with a field, which a pointer cast refuses.
+        let ptr: NonNull<dyn DowncastTrait> = NonNull::from(&mut object);
+        assert!(unsafe { cast_ptr::<dyn Downcasted>(ptr) }.is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn wrong_owned_answer() {
+        // The slot refuses the wrong type and hands the object back, so the cast fails without
+        // losing it.
+        let object: Box<dyn DowncastTrait> = Box::new(safe::Mislabeled(Downcastable { val: 0 }));
+        let object = downcast_trait_box!(dyn Downcasted, object).err().unwrap();
+        assert!(object.is::<safe::Mislabeled>());
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn wrong_delegated_answer() {
+        // The inner object answers the probe, so the wrapper is taken apart, and is rebuilt when
+        // the inner object refuses the cast.
+        struct Wrapper<T> {
+            label: u32,
+            inner: T,
+        }
+        impl<T: DowncastTrait + 'static> DowncastTrait for Wrapper<T> {
+            downcast_trait_impl_convert_to!(delegate = self.inner);
+        }
+        let object: Box<dyn DowncastTrait> = Box::new(Wrapper {
+            label: 1,
+            inner: safe::Mislabeled(Downcastable { val: 2 }),
+        });
+        let object = downcast_trait_box!(dyn Downcasted, object).err().unwrap();
+        let wrapper = object.downcast_ref::<Wrapper<safe::Mislabeled>>().unwrap();
+        assert_eq!((wrapper.label, wrapper.inner.0.val), (1, 2));
+
+        struct BoxWrapper {
+            label: u32,
+            inner: Box<dyn DowncastTrait>,
+        }
+        impl DowncastTrait for BoxWrapper {
+            downcast_trait_impl_convert_to!(delegate = self.inner);
+        }
+        let inner: Box<dyn DowncastTrait> = Box::new(safe::Mislabeled(Downcastable { val: 3 }));
+        let object: Box<dyn DowncastTrait> = Box::new(BoxWrapper { label: 4, inner });
+        let object = downcast_trait_box!(dyn Downcasted, object).err().unwrap();
+        let wrapper = object.downcast_ref::<BoxWrapper>().unwrap();
+        assert_eq!(wrapper.label, 4);
+        assert!(wrapper.inner.is::<safe::Mislabeled>());
     }
 }
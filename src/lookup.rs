@@ -0,0 +1,207 @@
+//! Lookup of the requested trait in long cast lists.
+//!
+//! Short cast lists are expanded into a chain of `TypeId` comparisons. Lists longer than
+//! [LOOKUP_THRESHOLD] are instead looked up in a hash table that is built the first time the list
+//! is used, and the matching entry is called through a function pointer.
+//!
+//! Without `std` the threshold is `usize::MAX`, so every list is expanded into a chain.
+use core::any::TypeId;
+#[cfg(feature = "std")]
+use core::{
+    hash::{Hash, Hasher},
+    ptr,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// Cast lists with more entries than this use a hash table instead of a comparison chain.
+#[cfg(feature = "std")]
+pub const LOOKUP_THRESHOLD: usize = 16;
+
+/// Cast lists with more entries than this use a hash table instead of a comparison chain. The
+/// table can not be cached without `std`, so the chain is always used.
+#[cfg(not(feature = "std"))]
+pub const LOOKUP_THRESHOLD: usize = usize::MAX;
+
+/// Marks an unused bucket in a [LookupTable].
+#[cfg(feature = "std")]
+const EMPTY: u32 = u32::MAX;
+
+/// A hasher that keeps the last integer written to it. `TypeId` is already a hash of the type,
+/// so its bits are used directly as the key into the table.
+#[cfg(feature = "std")]
+#[derive(Default)]
+pub(crate) struct IdentityHasher(u64);
+
+#[cfg(feature = "std")]
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ u64::from(*byte);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 ^= value;
+    }
+}
+
+#[cfg(feature = "std")]
+fn key(id: TypeId) -> u64 {
+    let mut hasher = IdentityHasher::default();
+    id.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The hash table of one type, linked to the tables built before it.
+#[cfg(feature = "std")]
+struct Table {
+    owner: TypeId,
+    buckets: Box<[u32]>,
+    next: *const Table,
+}
+
+/// Lazily built hash tables of the `TypeId`s in a cast list, mapping each to its index in the
+/// list. Collisions are resolved by probing the following buckets.
+///
+/// The tables live in a `static` inside the generated cast function, which is shared by all
+/// instantiations of a generic implementation. Their cast lists can differ, so each type builds a
+/// table of its own the first time it is cast. The tables are kept in a list that is only ever
+/// prepended to, and are never freed.
+pub struct LookupTable {
+    #[cfg(feature = "std")]
+    tables: AtomicPtr<Table>,
+}
+
+impl LookupTable {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        LookupTable {
+            #[cfg(feature = "std")]
+            tables: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Returns the index of `target` in `ids`, the cast list of the type `owner`.
+    #[cfg(feature = "std")]
+    pub fn index_of(&self, owner: TypeId, ids: &[TypeId], target: TypeId) -> Option<usize> {
+        let buckets = self.buckets(owner, ids);
+        let mask = buckets.len() - 1;
+        let mut bucket = key(target) as usize & mask;
+        loop {
+            let index = buckets[bucket];
+            if index == EMPTY {
+                return None;
+            }
+            if ids[index as usize] == target {
+                return Some(index as usize);
+            }
+            bucket = (bucket + 1) & mask;
+        }
+    }
+
+    /// Returns the index of `target` in `ids`, the cast list of the type `owner`.
+    #[cfg(not(feature = "std"))]
+    pub fn index_of(&self, _owner: TypeId, ids: &[TypeId], target: TypeId) -> Option<usize> {
+        ids.iter().position(|id| *id == target)
+    }
+
+    /// Returns the buckets of the table of `owner`, building it if this is the first cast of
+    /// `owner`.
+    #[cfg(feature = "std")]
+    fn buckets(&self, owner: TypeId, ids: &[TypeId]) -> &[u32] {
+        let mut next = self.tables.load(Ordering::Acquire);
+        // Safety: the tables are leaked, and are only linked in once they are complete.
+        while let Some(table) = unsafe { next.as_ref() } {
+            if table.owner == owner {
+                return &table.buckets;
+            }
+            next = table.next.cast_mut();
+        }
+        // At most half of the buckets are used, which keeps the probe sequences short.
+        let mut buckets = vec![EMPTY; (ids.len() * 2).next_power_of_two()];
+        let mask = buckets.len() - 1;
+        for (index, id) in ids.iter().enumerate() {
+            let mut bucket = key(*id) as usize & mask;
+            while buckets[bucket] != EMPTY {
+                bucket = (bucket + 1) & mask;
+            }
+            buckets[bucket] = index as u32;
+        }
+        let table = Box::leak(Box::new(Table {
+            owner,
+            buckets: buckets.into_boxed_slice(),
+            next: ptr::null(),
+        }));
+        // Another thread may link in a table for the same type first. Both are correct, and the
+        // one found first is used afterwards.
+        let mut head = self.tables.load(Ordering::Acquire);
+        loop {
+            table.next = head;
+            match self.tables.compare_exchange_weak(
+                head,
+                table,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return &table.buckets,
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+// Safety: the tables are immutable once linked in, and only hold plain data.
+#[cfg(feature = "std")]
+unsafe impl Sync for Table {}
+
+/// This macro is used internally by the `downcast_trait_impl_convert_to_*` macros to decide
+/// whether a cast list is long enough to use a hash table.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_use_table {
+    ($($target:ty),*) => {
+        <[&str]>::len(&[$(stringify!($target)),*]) > $crate::__private::LOOKUP_THRESHOLD
+    };
+}
+
+/// This macro is used internally by the `downcast_trait_impl_convert_to_*` macros to look up the
+/// requested trait in a hash table, and call the matching entry with `args`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __downcast_trait_lookup {
+    ($requested:expr, $fn_type:ty, ($($arg:expr),*), $miss:expr, $($target:ty => $entry:expr),*) => {{
+        const COUNT: usize = <[&str]>::len(&[$(stringify!($target)),*]);
+        static TABLE: $crate::__private::LookupTable = $crate::__private::LookupTable::new();
+        let ids: &'static [$crate::__private::TypeId; COUNT] =
+            const { &[$($crate::__private::TypeId::of::<$target>()),*] };
+        let entries: &'static [$fn_type; COUNT] = const { &[$($entry as $fn_type),*] };
+        match TABLE.index_of($crate::__private::TypeId::of::<Self>(), ids, $requested) {
+            $crate::__private::Some(index) => (entries[index])($($arg),*),
+            $crate::__private::None => $miss,
+        }
+    }};
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::LookupTable;
+    use core::any::TypeId;
+
+    #[test]
+    fn table_per_owner() {
+        static TABLE: LookupTable = LookupTable::new();
+        let first = [TypeId::of::<u8>(), TypeId::of::<u16>()];
+        let second = [TypeId::of::<u32>(), TypeId::of::<u8>(), TypeId::of::<u64>()];
+        let owner = TypeId::of::<[u8; 1]>();
+        let other = TypeId::of::<[u8; 2]>();
+        assert_eq!(TABLE.index_of(owner, &first, TypeId::of::<u16>()), Some(1));
+        assert_eq!(TABLE.index_of(other, &second, TypeId::of::<u8>()), Some(1));
+        assert_eq!(TABLE.index_of(other, &second, TypeId::of::<u16>()), None);
+        assert_eq!(TABLE.index_of(owner, &first, TypeId::of::<u64>()), None);
+        assert_eq!(TABLE.index_of(owner, &first, TypeId::of::<u8>()), Some(0));
+    }
+}
@@ -0,0 +1,202 @@
+//! Ordered dispatch over several trait casts with [match_trait](../macro.match_trait.html).
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
+use crate::DowncastTrait;
+
+/// A [DowncastTrait](../trait.DowncastTrait.html) object that can be tried against the arms of
+/// [match_trait](../macro.match_trait.html). A failed cast hands the source back for the next arm.
+#[doc(hidden)]
+pub trait TraitSource: Sized {
+    type Cast<T: ?Sized + 'static>;
+    fn try_cast<T: ?Sized + 'static>(self) -> Result<Self::Cast<T>, Self>;
+}
+
+impl<'a> TraitSource for &'a dyn DowncastTrait {
+    type Cast<T: ?Sized + 'static> = &'a T;
+
+    fn try_cast<T: ?Sized + 'static>(self) -> Result<&'a T, Self> {
+        crate::slot::cast_ref(self).ok_or(self)
+    }
+}
+
+impl<'a> TraitSource for &'a mut dyn DowncastTrait {
+    type Cast<T: ?Sized + 'static> = &'a mut T;
+
+    fn try_cast<T: ?Sized + 'static>(self) -> Result<&'a mut T, Self> {
+        crate::slot::try_cast_mut(self)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl TraitSource for Box<dyn DowncastTrait> {
+    type Cast<T: ?Sized + 'static> = Box<T>;
+
+    fn try_cast<T: ?Sized + 'static>(self) -> Result<Box<T>, Self> {
+        crate::slot::cast_box(self)
+    }
+}
+
+/// This macro tries a list of casts in order, and evaluates the arm of the first one that
+/// succeeds. The source can be a `&dyn DowncastTrait`, a `&mut dyn DowncastTrait` or a
+/// `Box<dyn DowncastTrait>`, and each arm binds the cast to a reference or box of the same kind.
+/// The last arm is required, and is taken when no cast succeeds. It can bind the unmatched
+/// source by name:
+/// ```
+/// use downcast_trait::prelude::*;
+/// trait Container {
+///     fn len(&self) -> usize;
+/// }
+/// trait Button {
+///     fn press(&mut self) -> bool;
+/// }
+/// struct Window;
+/// impl Container for Window {
+///     fn len(&self) -> usize {
+///         3
+///     }
+/// }
+/// impl DowncastTrait for Window {
+///     downcast_trait_impl_convert_to!(dyn Container);
+/// }
+///
+/// let mut window = Window;
+/// let len = match_trait!(window.to_downcast_trait() {
+///     dyn Button as _ => 0,
+///     dyn Container as container => container.len(),
+///     _ => 0,
+/// });
+/// assert_eq!(len, 3);
+///
+/// let unmatched = match_trait!(window.to_downcast_trait_mut() {
+///     dyn Button as button => Ok(button.press()),
+///     other => Err(other),
+/// });
+/// assert!(unmatched.unwrap_err().is::<Window>());
+/// ```
+///
+/// Boxed sources need the `alloc` feature. A boxed cast that fails hands the box back, so the
+/// last arm can still take ownership of the object.
+#[macro_export]
+macro_rules! match_trait
+{
+    ($($tokens:tt)+) => {
+        $crate::__match_trait!(@source [] $($tokens)+)
+    };
+}
+
+/// This macro is used internally by [match_trait](macro.match_trait.html) to split the source
+/// from the arms, and expand the arms into nested matches.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __match_trait
+{
+    (@source [$($source:tt)+] { $($arms:tt)+ }) => {{
+        let __downcast_trait_source = $($source)+;
+        $crate::__match_trait!(@arms __downcast_trait_source $($arms)+)
+    }};
+    (@source [$($source:tt)*] $next:tt $($rest:tt)+) => {
+        $crate::__match_trait!(@source [$($source)* $next] $($rest)+)
+    };
+    (@arms $source:ident _ => $fallback:expr $(,)?) => {
+        $fallback
+    };
+    (@arms $source:ident $unmatched:ident => $fallback:expr $(,)?) => {{
+        let $unmatched = $source;
+        $fallback
+    }};
+    (@arms $source:ident $target:ty as $bind:pat => $body:expr, $($arms:tt)+) => {
+        match $crate::__private::TraitSource::try_cast::<$target>($source) {
+            $crate::__private::Ok($bind) => $body,
+            $crate::__private::Err($source) => $crate::__match_trait!(@arms $source $($arms)+),
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use crate::{downcast_trait_impl_convert_to, DowncastTrait};
+    #[cfg(feature = "alloc")]
+    use std::boxed::Box;
+    use std::{
+        format,
+        string::{String, ToString},
+    };
+
+    trait Container {
+        fn len(&self) -> usize;
+    }
+    trait Button {
+        fn press(&mut self) -> u32;
+    }
+    trait Hidden {}
+
+    struct Toolbar {
+        presses: u32,
+    }
+    impl Container for Toolbar {
+        fn len(&self) -> usize {
+            4
+        }
+    }
+    impl Button for Toolbar {
+        fn press(&mut self) -> u32 {
+            self.presses += 1;
+            self.presses
+        }
+    }
+    impl DowncastTrait for Toolbar {
+        downcast_trait_impl_convert_to!(dyn Container, dyn Button);
+    }
+
+    #[derive(Debug)]
+    struct Plain;
+    impl DowncastTrait for Plain {
+        downcast_trait_impl_convert_to!(dyn core::fmt::Debug);
+    }
+
+    fn describe(object: &dyn DowncastTrait) -> String {
+        match_trait!(object {
+            dyn Hidden as _ => "hidden".to_string(),
+            dyn Container as container => format!("container of {}", container.len()),
+            dyn Button as _ => "button".to_string(),
+            _ => "unknown".to_string(),
+        })
+    }
+
+    #[test]
+    fn ordered_arms() {
+        let mut toolbar = Toolbar { presses: 0 };
+        assert_eq!(describe(toolbar.to_downcast_trait()), "container of 4");
+        assert_eq!(describe(Plain.to_downcast_trait()), "unknown");
+
+        let object = toolbar.to_downcast_trait_mut();
+        let pressed = match_trait!(object {
+            dyn Hidden as _ => 0,
+            dyn Button as button => button.press() + button.press(),
+            _ => 0,
+        });
+        assert_eq!(pressed, 3);
+        let missed = match_trait!(toolbar.to_downcast_trait_mut() {
+            dyn Hidden as _ => None,
+            unmatched => unmatched.downcast_mut::<Toolbar>().map(|t| t.presses),
+        });
+        assert_eq!(missed, Some(2));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn box_arms() {
+        let boxed: Box<dyn DowncastTrait> = Box::new(Toolbar { presses: 0 });
+        let boxed = match_trait!(boxed {
+            dyn Hidden as _ => panic!("Toolbar is not Hidden"),
+            other => other,
+        });
+        let mut button = match_trait!(boxed {
+            dyn Button as button => button,
+            _ => panic!("Toolbar is a Button"),
+        });
+        assert_eq!(button.press(), 1);
+    }
+}
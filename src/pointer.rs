@@ -0,0 +1,430 @@
+//! Owned casts of any smart pointer that implements [CastablePointer].
+//!
+//! The pointer is taken apart into a raw pointer to the object, which is cast with
+//! [cast_ptr](../fn.cast_ptr.html). The implementation answers with an unsizing of that raw
+//! pointer, so the result keeps the provenance of the allocation, and the pointer is rebuilt
+//! around it. Answers with a part of the object, such as projected traits and delegated casts,
+//! can not be rebuilt into the same kind of pointer and are refused.
+//!
+//! `Box`, `Rc` and `Arc` are cast with their own owned casts instead, so a cast of one of them
+//! succeeds exactly when the matching `downcast_trait_box!`, `downcast_trait_rc!` or
+//! `downcast_trait_arc!` would.
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, rc::Rc};
+use core::ptr::NonNull;
+
+use crate::DowncastTrait;
+
+/// A smart pointer that can be cast with [downcast_trait_ptr](macro.downcast_trait_ptr.html). It
+/// says how to split off the pointer to its value, and how to rebuild a pointer of the same kind
+/// to another type:
+/// ```
+/// use core::ptr::NonNull;
+/// use downcast_trait::prelude::*;
+///
+/// struct Handle<T: ?Sized>(NonNull<T>);
+/// unsafe impl<T: ?Sized> CastablePointer for Handle<T> {
+///     type Pointee = T;
+///     type Cast<U: ?Sized + 'static> = Handle<U>;
+///     fn into_raw(self) -> NonNull<T> {
+///         self.0
+///     }
+///     unsafe fn from_raw(raw: NonNull<T>) -> Self {
+///         Handle(raw)
+///     }
+/// }
+///
+/// trait Container {}
+/// struct Window;
+/// impl Container for Window {}
+/// impl DowncastTrait for Window {
+///     downcast_trait_impl_convert_to!(dyn Container);
+/// }
+///
+/// let handle: Handle<dyn DowncastTrait> = Handle(NonNull::from(Box::leak(Box::new(Window))));
+/// assert!(downcast_trait_ptr!(dyn Container, handle).is_ok());
+/// ```
+///
+/// It is implemented for `Box` and `Rc` with the `alloc` feature, and for `Arc` on targets with
+/// pointer sized atomics.
+///
+/// # Safety
+/// The pointer returned by [into_raw](#tymethod.into_raw) must point to the value, with the
+/// provenance needed by `from_raw`. [from_raw](#tymethod.from_raw) of `Self::Cast<U>` must accept
+/// that pointer after it is unsized to `U`, for a value of the same size and alignment at the same
+/// address.
+pub unsafe trait CastablePointer: Sized {
+    /// The type of the value the pointer points to.
+    type Pointee: ?Sized;
+    /// The same kind of pointer to a value of type `U`.
+    type Cast<U: ?Sized + 'static>: CastablePointer<Pointee = U>;
+    /// Gives up the pointer, returning the raw pointer to its value without dropping it.
+    fn into_raw(self) -> NonNull<Self::Pointee>;
+    /// Rebuilds the pointer from the raw pointer to its value.
+    ///
+    /// # Safety
+    /// `raw` must be returned by [into_raw](#tymethod.into_raw) of this kind of pointer, with
+    /// the metadata of any type of the same size and alignment as the value.
+    unsafe fn from_raw(raw: NonNull<Self::Pointee>) -> Self;
+
+    /// Casts a pointer to a [DowncastTrait](../trait.DowncastTrait.html) object to the same kind
+    /// of pointer to `T`, handing it back if the cast is not supported. This is used by
+    /// [downcast_trait_ptr](macro.downcast_trait_ptr.html) and should not be called directly.
+    ///
+    /// By default the object is cast with [cast_ptr](../fn.cast_ptr.html), which only succeeds if
+    /// it answers with itself. `Box`, `Rc` and `Arc` use the owned casts of
+    /// [downcast_trait_box](macro.downcast_trait_box.html) and the like instead, so both macros
+    /// agree on them.
+    fn cast<T: ?Sized + 'static>(self) -> Result<Self::Cast<T>, Self>
+    where
+        Self: CastablePointer<Pointee = dyn DowncastTrait>,
+    {
+        let raw = self.into_raw();
+        // Safety: the pointer owns or shares the object until it is rebuilt below, and is not
+        // used mutably in the meantime.
+        match unsafe { crate::slot::cast_ptr::<T>(raw) } {
+            // Safety: the cast is derived from raw and points to the whole object, as a value of
+            // the same size and alignment.
+            Some(cast) => Ok(unsafe { <Self::Cast<T>>::from_raw(cast) }),
+            // Safety: raw is unchanged.
+            None => Err(unsafe { Self::from_raw(raw) }),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T: ?Sized> CastablePointer for Box<T> {
+    type Pointee = T;
+    type Cast<U: ?Sized + 'static> = Box<U>;
+
+    fn into_raw(self) -> NonNull<T> {
+        // Safety: the pointer of a box is never null.
+        unsafe { NonNull::new_unchecked(Box::into_raw(self)) }
+    }
+
+    unsafe fn from_raw(raw: NonNull<T>) -> Self {
+        Box::from_raw(raw.as_ptr())
+    }
+
+    fn cast<U: ?Sized + 'static>(self) -> Result<Self::Cast<U>, Self>
+    where
+        Self: CastablePointer<Pointee = dyn DowncastTrait>,
+    {
+        // Safety: the pointers are only taken apart to be rebuilt as the same kind of pointer to
+        // the same value, which the bound on `Self` does not let the compiler see.
+        unsafe {
+            match crate::slot::cast_box::<U>(Box::from_raw(self.into_raw().as_ptr())) {
+                Ok(cast) => Ok(<Self::Cast<U>>::from_raw(CastablePointer::into_raw(cast))),
+                Err(object) => Err(<Self as CastablePointer>::from_raw(
+                    CastablePointer::into_raw(object),
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+unsafe impl<T: ?Sized> CastablePointer for Rc<T> {
+    type Pointee = T;
+    type Cast<U: ?Sized + 'static> = Rc<U>;
+
+    fn into_raw(self) -> NonNull<T> {
+        // Safety: the pointer of an Rc is never null.
+        unsafe { NonNull::new_unchecked(Rc::into_raw(self) as *mut T) }
+    }
+
+    unsafe fn from_raw(raw: NonNull<T>) -> Self {
+        Rc::from_raw(raw.as_ptr())
+    }
+
+    fn cast<U: ?Sized + 'static>(self) -> Result<Self::Cast<U>, Self>
+    where
+        Self: CastablePointer<Pointee = dyn DowncastTrait>,
+    {
+        // Safety: the pointers are only taken apart to be rebuilt as the same kind of pointer to
+        // the same value, which the bound on `Self` does not let the compiler see.
+        unsafe {
+            match crate::slot::cast_rc::<U>(Rc::from_raw(self.into_raw().as_ptr())) {
+                Ok(cast) => Ok(<Self::Cast<U>>::from_raw(CastablePointer::into_raw(cast))),
+                Err(object) => Err(<Self as CastablePointer>::from_raw(
+                    CastablePointer::into_raw(object),
+                )),
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+unsafe impl<T: ?Sized> CastablePointer for Arc<T> {
+    type Pointee = T;
+    type Cast<U: ?Sized + 'static> = Arc<U>;
+
+    fn into_raw(self) -> NonNull<T> {
+        // Safety: the pointer of an Arc is never null.
+        unsafe { NonNull::new_unchecked(Arc::into_raw(self) as *mut T) }
+    }
+
+    unsafe fn from_raw(raw: NonNull<T>) -> Self {
+        Arc::from_raw(raw.as_ptr())
+    }
+
+    fn cast<U: ?Sized + 'static>(self) -> Result<Self::Cast<U>, Self>
+    where
+        Self: CastablePointer<Pointee = dyn DowncastTrait>,
+    {
+        // Safety: the pointers are only taken apart to be rebuilt as the same kind of pointer to
+        // the same value, which the bound on `Self` does not let the compiler see.
+        unsafe {
+            match crate::slot::cast_arc::<U>(Arc::from_raw(self.into_raw().as_ptr())) {
+                Ok(cast) => Ok(<Self::Cast<U>>::from_raw(CastablePointer::into_raw(cast))),
+                Err(object) => Err(<Self as CastablePointer>::from_raw(
+                    CastablePointer::into_raw(object),
+                )),
+            }
+        }
+    }
+}
+
+/// Casts a smart pointer to a [DowncastTrait](../trait.DowncastTrait.html) object to the same
+/// kind of pointer to `T`, handing the original pointer back if the cast is not supported.
+pub fn cast_pointer<P, T>(src: P) -> Result<P::Cast<T>, P>
+where
+    P: CastablePointer<Pointee = dyn DowncastTrait>,
+    T: ?Sized + 'static,
+{
+    src.cast::<T>()
+}
+
+/// This macro can be used to cast any smart pointer implementing [CastablePointer] to a
+/// [DowncastTrait] object, to the same kind of pointer to an implemented trait. If the trait is
+/// not supported, the original pointer is returned as the error e.g:
+/// ```ignore
+/// match downcast_trait_ptr!(dyn Container, handle)
+/// {
+///   Ok(container) => {} //Use downcasted trait
+///   Err(handle) => {} //Try another trait
+/// }
+/// ```
+///
+/// `Box`, `Rc` and `Arc` are cast like with [downcast_trait_box](macro.downcast_trait_box.html),
+/// [downcast_trait_rc](macro.downcast_trait_rc.html) and
+/// [downcast_trait_arc](macro.downcast_trait_arc.html). Other pointers are only rebuilt if the
+/// object answers with itself, through [provide_ptr](struct.TraitSlot.html#method.provide_ptr),
+/// so projected traits and casts forwarded to a `delegate` field are refused, as are the parts of
+/// a [Composite](struct.Composite.html).
+#[macro_export]
+macro_rules! downcast_trait_ptr {
+    ($target:ty, $src:expr) => {
+        $crate::__private::cast_pointer::<_, $target>($src)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use core::{cell::Cell, ptr::NonNull};
+    use std::boxed::Box;
+
+    use super::CastablePointer;
+    use crate::{downcast_trait, downcast_trait_impl_convert_to, DowncastTrait};
+    #[cfg(feature = "alloc")]
+    use crate::{downcast_trait_arc, downcast_trait_box, downcast_trait_rc, Composite};
+    #[cfg(feature = "alloc")]
+    use std::{rc::Rc, sync::Arc};
+
+    trait Label {
+        fn text(&self) -> &str;
+    }
+    trait Measured {
+        fn size(&self) -> (u32, u32);
+    }
+    trait Hidden {}
+
+    struct Button {
+        size: (u32, u32),
+        drops: &'static std::thread::LocalKey<Cell<u32>>,
+    }
+    impl Label for Button {
+        fn text(&self) -> &str {
+            "button"
+        }
+    }
+    impl Measured for (u32, u32) {
+        fn size(&self) -> (u32, u32) {
+            *self
+        }
+    }
+    impl Drop for Button {
+        fn drop(&mut self) {
+            self.drops.with(|drops| drops.set(drops.get() + 1));
+        }
+    }
+    impl DowncastTrait for Button {
+        downcast_trait_impl_convert_to!(dyn Label, dyn Measured => self.size);
+    }
+
+    std::thread_local! {
+        static DROPS: Cell<u32> = const { Cell::new(0) };
+    }
+
+    /// A pointer that owns a leaked box, like the handles of an arena.
+    struct Handle<T: ?Sized>(NonNull<T>);
+    unsafe impl<T: ?Sized> CastablePointer for Handle<T> {
+        type Pointee = T;
+        type Cast<U: ?Sized + 'static> = Handle<U>;
+        fn into_raw(self) -> NonNull<T> {
+            let raw = self.0;
+            core::mem::forget(self);
+            raw
+        }
+        unsafe fn from_raw(raw: NonNull<T>) -> Self {
+            Handle(raw)
+        }
+    }
+    impl<T: ?Sized> Drop for Handle<T> {
+        fn drop(&mut self) {
+            drop(unsafe { Box::from_raw(self.0.as_ptr()) });
+        }
+    }
+
+    #[test]
+    fn custom_pointer() {
+        let button = Box::new(Button {
+            size: (2, 3),
+            drops: &DROPS,
+        });
+        let handle: Handle<dyn DowncastTrait> = Handle(NonNull::from(Box::leak(button)));
+        let Err(handle) = downcast_trait_ptr!(dyn Hidden, handle) else {
+            panic!("cast should fail")
+        };
+        // The size is a field of the button, and can not be rebuilt into a handle of its own.
+        let Err(handle) = downcast_trait_ptr!(dyn Measured, handle) else {
+            panic!("cast should fail")
+        };
+        let object = unsafe { handle.0.as_ref() };
+        assert_eq!(
+            downcast_trait!(dyn Measured, object).unwrap().size(),
+            (2, 3)
+        );
+        let address = handle.0.as_ptr() as *mut u8;
+        let Ok(label) = downcast_trait_ptr!(dyn Label, handle) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(unsafe { label.0.as_ref() }.text(), "button");
+        assert_eq!(label.0.as_ptr() as *mut u8, address);
+        drop(label);
+        assert_eq!(DROPS.with(Cell::get), 1);
+    }
+
+    #[test]
+    fn delegated_pointer() {
+        struct Bordered {
+            width: u32,
+            inner: Button,
+        }
+        impl DowncastTrait for Bordered {
+            downcast_trait_impl_convert_to!(delegate = self.inner);
+        }
+
+        let bordered = Box::new(Bordered {
+            width: 1,
+            inner: Button {
+                size: (2, 3),
+                drops: &DROPS,
+            },
+        });
+        let handle: Handle<dyn DowncastTrait> = Handle(NonNull::from(Box::leak(bordered)));
+        // The button answers for the bordered object, but only a part of it is a button.
+        let Err(handle) = downcast_trait_ptr!(dyn Label, handle) else {
+            panic!("cast should fail")
+        };
+        let object = unsafe { handle.0.as_ref() };
+        assert_eq!(downcast_trait!(dyn Label, object).unwrap().text(), "button");
+        assert_eq!(object.downcast_ref::<Bordered>().unwrap().width, 1);
+        drop(handle);
+        assert_eq!(DROPS.with(Cell::get), 1);
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn builtin_pointers() {
+        let boxed: Box<dyn DowncastTrait> = Box::new(Button {
+            size: (4, 5),
+            drops: &DROPS,
+        });
+        let boxed = downcast_trait_ptr!(dyn Hidden, boxed).err().unwrap();
+        assert_eq!(
+            downcast_trait_ptr!(dyn Label, boxed).ok().unwrap().text(),
+            "button"
+        );
+
+        let shared: std::rc::Rc<dyn DowncastTrait> = std::rc::Rc::new(Button {
+            size: (0, 0),
+            drops: &DROPS,
+        });
+        let label = downcast_trait_ptr!(dyn Label, shared.clone()).ok().unwrap();
+        assert_eq!(std::rc::Rc::strong_count(&label), 2);
+        assert!(core::ptr::addr_eq(&*label, &*shared));
+        drop(shared);
+        assert_eq!(label.text(), "button");
+
+        let shared: std::sync::Arc<dyn DowncastTrait> = std::sync::Arc::new(Button {
+            size: (0, 0),
+            drops: &DROPS,
+        });
+        let Ok(label) = downcast_trait_ptr!(dyn Label, shared) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(label.text(), "button");
+    }
+
+    #[test]
+    #[cfg(feature = "alloc")]
+    fn builtin_pointers_agree() {
+        struct Bordered {
+            inner: Button,
+        }
+        impl DowncastTrait for Bordered {
+            downcast_trait_impl_convert_to!(delegate = self.inner);
+        }
+        let bordered = || -> Box<dyn DowncastTrait> {
+            Box::new(Bordered {
+                inner: Button {
+                    size: (2, 3),
+                    drops: &DROPS,
+                },
+            })
+        };
+        let composite = || -> Box<dyn DowncastTrait> {
+            Box::new(Composite::new().with::<dyn Measured>(Box::new((4, 5))))
+        };
+
+        // Delegated and composite casts are answered by both macros.
+        let by_box = downcast_trait_box!(dyn Label, bordered()).ok().unwrap();
+        let by_ptr = downcast_trait_ptr!(dyn Label, bordered()).ok().unwrap();
+        assert_eq!(by_box.text(), by_ptr.text());
+        let by_box = downcast_trait_box!(dyn Measured, composite()).ok().unwrap();
+        let by_ptr = downcast_trait_ptr!(dyn Measured, composite()).ok().unwrap();
+        assert_eq!(by_box.size(), by_ptr.size());
+
+        // Projected casts, which a box can not answer, and unsupported ones are refused by both.
+        let by_box = downcast_trait_box!(dyn Measured, bordered()).err().unwrap();
+        let by_ptr = downcast_trait_ptr!(dyn Measured, bordered()).err().unwrap();
+        assert!(by_box.is::<Bordered>() && by_ptr.is::<Bordered>());
+        let by_box = downcast_trait_box!(dyn Hidden, composite()).err().unwrap();
+        let by_ptr = downcast_trait_ptr!(dyn Hidden, composite()).err().unwrap();
+        assert!(by_box.is::<Composite>() && by_ptr.is::<Composite>());
+
+        // Shared pointers can not move a part out, so both refuse the same casts.
+        let shared: Rc<dyn DowncastTrait> = Rc::from(bordered());
+        let by_rc = downcast_trait_rc!(dyn Label, shared.clone()).err().unwrap();
+        let by_ptr = downcast_trait_ptr!(dyn Label, by_rc).err().unwrap();
+        assert!(Rc::ptr_eq(&by_ptr, &shared));
+        let shared: Arc<dyn DowncastTrait> = Arc::from(composite());
+        assert!(downcast_trait_arc!(Composite, shared.clone()).is_ok());
+        assert!(downcast_trait_ptr!(Composite, shared.clone()).is_ok());
+        assert!(downcast_trait_ptr!(dyn Measured, shared).is_err());
+    }
+}
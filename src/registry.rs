@@ -0,0 +1,293 @@
+//! A runtime registry of casts, for types that do not implement
+//! [DowncastTrait](../trait.DowncastTrait.html) themselves.
+use core::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use crate::DowncastTrait;
+
+/// The casts from one concrete type `C` to one trait object type `T`.
+struct Casters<C, T: ?Sized> {
+    cast_ref: fn(&C) -> &T,
+    cast_mut: fn(&mut C) -> &mut T,
+    cast_box: fn(Box<C>) -> Box<T>,
+}
+
+/// The casts to `T` with the concrete type erased, so entries for different types can share a map.
+trait ErasedCasters<T: ?Sized>: Send + Sync {
+    fn cast_ref<'a>(&self, src: &'a dyn Any) -> Option<&'a T>;
+    fn cast_mut<'a>(&self, src: &'a mut dyn Any) -> Option<&'a mut T>;
+    fn cast_box(&self, src: Box<dyn Any>) -> Result<Box<T>, Box<dyn Any>>;
+    fn cast_object_ref<'a>(&self, src: &'a dyn DowncastTrait) -> Option<&'a T>;
+    fn cast_object_mut<'a>(&self, src: &'a mut dyn DowncastTrait) -> Option<&'a mut T>;
+    fn cast_object_box(
+        &self,
+        src: Box<dyn DowncastTrait>,
+    ) -> Result<Box<T>, Box<dyn DowncastTrait>>;
+}
+
+impl<C: 'static, T: ?Sized + 'static> ErasedCasters<T> for Casters<C, T> {
+    fn cast_ref<'a>(&self, src: &'a dyn Any) -> Option<&'a T> {
+        src.downcast_ref::<C>().map(self.cast_ref)
+    }
+
+    fn cast_mut<'a>(&self, src: &'a mut dyn Any) -> Option<&'a mut T> {
+        src.downcast_mut::<C>().map(self.cast_mut)
+    }
+
+    fn cast_box(&self, src: Box<dyn Any>) -> Result<Box<T>, Box<dyn Any>> {
+        src.downcast::<C>().map(self.cast_box)
+    }
+
+    fn cast_object_ref<'a>(&self, src: &'a dyn DowncastTrait) -> Option<&'a T> {
+        src.downcast_ref::<C>().map(self.cast_ref)
+    }
+
+    fn cast_object_mut<'a>(&self, src: &'a mut dyn DowncastTrait) -> Option<&'a mut T> {
+        src.downcast_mut::<C>().map(self.cast_mut)
+    }
+
+    fn cast_object_box(
+        &self,
+        src: Box<dyn DowncastTrait>,
+    ) -> Result<Box<T>, Box<dyn DowncastTrait>> {
+        src.downcast_box::<C>().map(self.cast_box)
+    }
+}
+
+/// A registry of casts from concrete types to traits that is filled in explicitly at runtime.
+///
+/// This can be used for types that can not implement [DowncastTrait](trait.DowncastTrait.html),
+/// such as types from other crates or objects that are only available as `dyn Any`. It can also be
+/// used as a fallback for [DowncastTrait](trait.DowncastTrait.html) objects whose static cast list
+/// misses a trait. The casts are normally registered with the
+/// [downcast_trait_register](macro.downcast_trait_register.html) macro when the application starts:
+/// ```
+/// # use downcast_trait::prelude::*;
+/// # use std::any::Any;
+/// trait Describe {
+///     fn describe(&self) -> String;
+/// }
+/// impl Describe for String {
+///     fn describe(&self) -> String {
+///         format!("string of length {}", self.len())
+///     }
+/// }
+/// let mut registry = CastRegistry::new();
+/// downcast_trait_register!(registry, String => dyn Describe);
+///
+/// let value: Box<dyn Any> = Box::new(String::from("abc"));
+/// let describe = registry.cast_ref::<dyn Describe>(value.as_ref()).unwrap();
+/// assert_eq!(describe.describe(), "string of length 3");
+/// ```
+#[derive(Default)]
+pub struct CastRegistry {
+    casters: HashMap<(TypeId, TypeId), Box<dyn Any + Send + Sync>>,
+}
+
+impl CastRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the casts from the concrete type `C` to the trait object type `T`. The functions
+    /// are usually the identity closure `|v| v`, which coerces `C` to `T`. A registration for the
+    /// same pair of types replaces the previous one.
+    pub fn register<C: 'static, T: ?Sized + 'static>(
+        &mut self,
+        cast_ref: fn(&C) -> &T,
+        cast_mut: fn(&mut C) -> &mut T,
+        cast_box: fn(Box<C>) -> Box<T>,
+    ) -> &mut Self {
+        let casters: Box<dyn ErasedCasters<T>> = Box::new(Casters {
+            cast_ref,
+            cast_mut,
+            cast_box,
+        });
+        self.casters
+            .insert((TypeId::of::<C>(), TypeId::of::<T>()), Box::new(casters));
+        self
+    }
+
+    /// Returns true if a cast from the concrete type with the id `concrete` to `T` is registered.
+    pub fn can_cast<T: ?Sized + 'static>(&self, concrete: TypeId) -> bool {
+        self.casters.contains_key(&(concrete, TypeId::of::<T>()))
+    }
+
+    fn casters<T: ?Sized + 'static>(&self, concrete: TypeId) -> Option<&dyn ErasedCasters<T>> {
+        self.casters
+            .get(&(concrete, TypeId::of::<T>()))
+            .and_then(|casters| casters.downcast_ref::<Box<dyn ErasedCasters<T>>>())
+            .map(|casters| casters.as_ref())
+    }
+
+    /// Casts `src` to `T` using a registered cast for its concrete type.
+    pub fn cast_ref<'a, T: ?Sized + 'static>(&self, src: &'a dyn Any) -> Option<&'a T> {
+        self.casters::<T>((*src).type_id())?.cast_ref(src)
+    }
+
+    /// Casts `src` to `T` using a registered cast for its concrete type.
+    pub fn cast_mut<'a, T: ?Sized + 'static>(&self, src: &'a mut dyn Any) -> Option<&'a mut T> {
+        self.casters::<T>((*src).type_id())?.cast_mut(src)
+    }
+
+    /// Casts `src` to `Box<T>` using a registered cast for its concrete type, handing the original
+    /// box back if no cast is registered.
+    pub fn cast_box<T: ?Sized + 'static>(&self, src: Box<dyn Any>) -> Result<Box<T>, Box<dyn Any>> {
+        match self.casters::<T>((*src).type_id()) {
+            Some(casters) => casters.cast_box(src),
+            None => Err(src),
+        }
+    }
+
+    /// Casts `src` to `T` using its own cast list, falling back to a registered cast for its
+    /// concrete type if the list does not contain `T`.
+    pub fn downcast_trait_ref<'a, T: ?Sized + 'static>(
+        &self,
+        src: &'a dyn DowncastTrait,
+    ) -> Option<&'a T> {
+        crate::slot::cast_ref(src)
+            .or_else(|| self.casters::<T>((*src).type_id())?.cast_object_ref(src))
+    }
+
+    /// Casts `src` to `T` using its own cast list, falling back to a registered cast for its
+    /// concrete type if the list does not contain `T`.
+    pub fn downcast_trait_mut<'a, T: ?Sized + 'static>(
+        &self,
+        src: &'a mut dyn DowncastTrait,
+    ) -> Option<&'a mut T> {
+        match crate::slot::try_cast_mut(src) {
+            Ok(cast) => Some(cast),
+            Err(src) => self.casters::<T>((*src).type_id())?.cast_object_mut(src),
+        }
+    }
+
+    /// Casts `src` to `Box<T>` using its own cast list, falling back to a registered cast for its
+    /// concrete type if the list does not contain `T`. The original box is handed back if neither
+    /// can cast it.
+    pub fn downcast_trait_box<T: ?Sized + 'static>(
+        &self,
+        src: Box<dyn DowncastTrait>,
+    ) -> Result<Box<T>, Box<dyn DowncastTrait>> {
+        match crate::slot::cast_box(src) {
+            Ok(cast) => Ok(cast),
+            Err(src) => match self.casters::<T>((*src).type_id()) {
+                Some(casters) => casters.cast_object_box(src),
+                None => Err(src),
+            },
+        }
+    }
+}
+
+/// This macro registers casts from a concrete type to one or more traits in a
+/// [CastRegistry](struct.CastRegistry.html) e.g:
+/// ```ignore
+/// let mut registry = CastRegistry::new();
+/// downcast_trait_register!(registry, Window => dyn Container, dyn Scrollable);
+/// ```
+#[macro_export]
+macro_rules! downcast_trait_register {
+    ($registry:expr, $concrete:ty => $($target:ty),+) => {{
+        $(
+        $registry.register::<$concrete, $target>(|v| v, |v| v, |v| v);
+        )+
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{downcast_trait_impl_convert_to, CastTarget};
+
+    trait Named {
+        fn name(&self) -> String;
+    }
+    impl CastTarget for dyn Named {}
+
+    /// A type that does not implement DowncastTrait, standing in for a type from another crate.
+    struct Foreign(u32);
+    impl Named for Foreign {
+        fn name(&self) -> String {
+            format!("foreign {}", self.0)
+        }
+    }
+
+    struct Local(u32);
+    impl Named for Local {
+        fn name(&self) -> String {
+            format!("local {}", self.0)
+        }
+    }
+    impl DowncastTrait for Local {
+        downcast_trait_impl_convert_to!(dyn core::fmt::Debug);
+    }
+    impl core::fmt::Debug for Local {
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+            write!(f, "Local")
+        }
+    }
+
+    fn registry() -> CastRegistry {
+        let mut registry = CastRegistry::new();
+        downcast_trait_register!(registry, Foreign => dyn Named);
+        downcast_trait_register!(registry, Local => dyn Named);
+        registry
+    }
+
+    #[test]
+    fn any_casts() {
+        let registry = registry();
+        assert!(registry.can_cast::<dyn Named>(TypeId::of::<Foreign>()));
+        assert!(!registry.can_cast::<dyn Named>(TypeId::of::<u32>()));
+
+        let mut foreign = Foreign(1);
+        let any: &mut dyn Any = &mut foreign;
+        assert_eq!(
+            registry.cast_ref::<dyn Named>(any).map(|n| n.name()),
+            Some("foreign 1".to_string())
+        );
+        assert!(registry.cast_ref::<dyn core::fmt::Debug>(any).is_none());
+        registry.cast_mut::<dyn Named>(any).unwrap();
+        assert!(registry.cast_mut::<dyn Named>(&mut 3u32).is_none());
+
+        let boxed: Box<dyn Any> = Box::new(Foreign(2));
+        let Ok(named) = registry.cast_box::<dyn Named>(boxed) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(named.name(), "foreign 2");
+        let unregistered: Box<dyn Any> = Box::new(3u32);
+        let Err(unregistered) = registry.cast_box::<dyn Named>(unregistered) else {
+            panic!("cast should fail")
+        };
+        assert_eq!(unregistered.downcast_ref::<u32>(), Some(&3));
+    }
+
+    #[test]
+    fn fallback_casts() {
+        let registry = registry();
+        let mut local = Local(4);
+        let object: &mut dyn DowncastTrait = &mut local;
+        assert!(object.cast_ref::<dyn Named>().is_none());
+        assert_eq!(
+            registry
+                .downcast_trait_ref::<dyn Named>(object)
+                .map(|n| n.name()),
+            Some("local 4".to_string())
+        );
+        assert!(registry
+            .downcast_trait_ref::<dyn core::fmt::Debug>(object)
+            .is_some());
+        assert!(registry.downcast_trait_mut::<dyn Named>(object).is_some());
+
+        let boxed: Box<dyn DowncastTrait> = Box::new(Local(5));
+        let Ok(named) = registry.downcast_trait_box::<dyn Named>(boxed) else {
+            panic!("cast should succeed")
+        };
+        assert_eq!(named.name(), "local 5");
+        let boxed: Box<dyn DowncastTrait> = Box::new(Local(6));
+        let Err(boxed) = registry.downcast_trait_box::<dyn core::fmt::Display>(boxed) else {
+            panic!("cast should fail")
+        };
+        assert!(boxed.is::<Local>());
+    }
+}
@@ -0,0 +1,424 @@
+//! Type erased out-parameter used to pass trait objects out of a
+//! [DowncastTrait](../trait.DowncastTrait.html) implementation.
+//!
+//! The caller allocates a typed output on its own stack and hands the implementation a
+//! [TraitSlot] that refers to it as `dyn Any`. The implementation can only store a value in the
+//! slot if the value has exactly the type the caller asked for, so no fat pointer is ever
+//! reinterpreted as another type.
+use core::{
+    any::{Any, TypeId},
+    marker::PhantomData,
+    mem::{align_of, align_of_val, size_of, size_of_val},
+    pin::Pin,
+    ptr::{self, NonNull},
+};
+
+use crate::DowncastTrait;
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, rc::Rc};
+
+/// Output storage for a shared trait object reference.
+struct RefOut<T: ?Sized + 'static>(Option<NonNull<T>>);
+
+/// Output storage for a mutable trait object reference.
+struct MutOut<T: ?Sized + 'static>(Option<NonNull<T>>);
+
+/// Output storage for a pinned mutable trait object reference.
+struct PinOut<T: ?Sized + 'static>(Option<NonNull<T>>);
+
+/// Output storage for a pointer to the whole object. `data` points to the object being cast, and
+/// `size` and `align` are its layout.
+struct PtrOut<T: ?Sized + 'static> {
+    data: NonNull<()>,
+    size: usize,
+    align: usize,
+    answer: Option<NonNull<T>>,
+}
+
+/// Output storage of a probe, which only records whether the requested trait is supported.
+struct ProbeOut;
+
+/// Output storage of a probe for owned casts, which only records whether a boxed cast to the
+/// requested trait would be answered.
+#[cfg(feature = "alloc")]
+struct OwnedProbeOut;
+
+/// Output storage for a boxed trait object.
+#[cfg(feature = "alloc")]
+struct BoxOut<T: ?Sized + 'static>(Option<Box<T>>);
+
+/// Output storage for a pinned boxed trait object.
+#[cfg(feature = "alloc")]
+struct PinBoxOut<T: ?Sized + 'static>(Option<Pin<Box<T>>>);
+
+/// Output storage for a reference counted trait object.
+#[cfg(feature = "alloc")]
+struct RcOut<T: ?Sized + 'static>(Option<Rc<T>>);
+
+/// Output storage for an atomically reference counted trait object.
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+struct ArcOut<T: ?Sized + 'static>(Option<Arc<T>>);
+
+/// An erased slot that receives a trait object, keyed by the `TypeId` of the requested trait.
+///
+/// A slot is created by the casting functions and passed to the `convert_to_trait*` functions of
+/// [DowncastTrait](../trait.DowncastTrait.html). Implementations compare [target](#method.target)
+/// with the traits they support and answer with one of the `provide_*` functions.
+///
+/// The lifetime `'s` is the borrow of the object being cast in a shared, mutable or pinned cast. Only
+/// references living that long can be provided, so an implementation can answer with the object
+/// or its fields but not with a temporary, and no answer can be of the wrong type.
+pub struct TraitSlot<'s> {
+    target: TypeId,
+    out: &'s mut dyn Any,
+    filled: bool,
+}
+
+/// Proof that an owned cast was answered, returned by the owned `provide_*` functions of
+/// [TraitSlot].
+///
+/// It can not be created in any other way, and is tied to the slot it came from, so the boxed,
+/// `Rc` and `Arc` casts of [DowncastTrait](../trait.DowncastTrait.html) can only return `Ok` after
+/// handing the object over.
+pub struct Provided<'s>(PhantomData<fn(&'s ()) -> &'s ()>);
+
+impl<'s> Provided<'s> {
+    /// Only called by slots, right after storing a value.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn new() -> Self {
+        Provided(PhantomData)
+    }
+}
+
+impl<'s> TraitSlot<'s> {
+    fn new<T: ?Sized + 'static>(out: &'s mut dyn Any) -> Self {
+        TraitSlot {
+            target: TypeId::of::<T>(),
+            out,
+            filled: false,
+        }
+    }
+
+    /// The `TypeId` of the trait object type that is requested, e.g. `TypeId::of::<dyn Container>()`.
+    pub fn target(&self) -> TypeId {
+        self.target
+    }
+
+    /// Returns true if one of the `provide_*` functions has stored a value in the slot.
+    pub fn is_filled(&self) -> bool {
+        self.filled
+    }
+
+    /// Returns true if the slot was created by a shared cast that only asks whether a boxed cast
+    /// to [target](#method.target) would be answered. An implementation that answers the shared
+    /// cast with a field, which a boxed cast can not hand over, should not answer such a probe.
+    pub fn is_owned_probe(&self) -> bool {
+        #[cfg(feature = "alloc")]
+        return self.out.is::<OwnedProbeOut>();
+        #[cfg(not(feature = "alloc"))]
+        return false;
+    }
+
+    /// Stores a shared reference in a slot created by a shared cast. Does nothing if `T` is not
+    /// the requested type, or if the slot was created for a mutable or boxed cast.
+    pub fn provide_ref<T: ?Sized + 'static>(&mut self, value: &'s T) {
+        if let Some(out) = self.out.downcast_mut::<RefOut<T>>() {
+            out.0 = Some(NonNull::from(value));
+            self.filled = true;
+        } else if (self.out.is::<ProbeOut>() || self.is_owned_probe())
+            && self.target == TypeId::of::<T>()
+        {
+            self.filled = true;
+        }
+    }
+
+    /// Stores a pointer to the whole object in a slot created by a pointer cast. `value` is the
+    /// object, and `unsize` is the unsizing coercion `|this| this`. Does nothing if `T` is not the
+    /// requested type, if `value` does not cover the whole object, or if the slot was created for
+    /// another kind of cast.
+    ///
+    /// `unsize` is applied to the pointer the cast was made with, so the result keeps the
+    /// provenance of that pointer. A pinned `NonNull` can be coerced, but it can not be created,
+    /// taken apart or dereferenced by safe code, so `unsize` can only answer with the pointer it
+    /// was given, as a pointer to `S`.
+    pub fn provide_ptr<T: ?Sized + 'static, S>(
+        &mut self,
+        value: &'s S,
+        unsize: fn(Pin<NonNull<S>>) -> Pin<NonNull<T>>,
+    ) {
+        if let Some(out) = self.out.downcast_mut::<PtrOut<T>>() {
+            let whole = ptr::addr_eq(value, out.data.as_ptr())
+                && size_of::<S>() == out.size
+                && align_of::<S>() == out.align;
+            if !whole {
+                return;
+            }
+            let data = out.data.cast::<S>();
+            // Safety: Pin is a transparent wrapper, and the pinned pointer is never dereferenced.
+            let pinned: Pin<NonNull<S>> = unsafe { ptr::read((&data as *const NonNull<S>).cast()) };
+            let answer = unsize(pinned);
+            // Safety: as above.
+            let answer: NonNull<T> =
+                unsafe { ptr::read((&answer as *const Pin<NonNull<T>>).cast()) };
+            if ptr::addr_eq(answer.as_ptr(), data.as_ptr()) {
+                out.answer = Some(answer);
+                self.filled = true;
+            }
+        }
+    }
+
+    /// Stores a mutable reference in a slot created by a mutable cast. Does nothing if `T` is not
+    /// the requested type, or if the slot was created for a shared or boxed cast.
+    pub fn provide_mut<T: ?Sized + 'static>(&mut self, value: &'s mut T) {
+        if let Some(out) = self.out.downcast_mut::<MutOut<T>>() {
+            out.0 = Some(NonNull::from(value));
+            self.filled = true;
+        }
+    }
+
+    /// Stores a pinned mutable reference in a slot created by a pinned cast. Does nothing if `T`
+    /// is not the requested type, or if the slot was created for another kind of cast.
+    ///
+    /// A safe implementation can only pass on the pinned `self` it was given, so the pinning
+    /// guarantee of the object carries over to the result. A field may only be provided through
+    /// a pin projection, which requires the field to be structurally pinned.
+    pub fn provide_pin<T: ?Sized + 'static>(&mut self, value: Pin<&'s mut T>) {
+        if let Some(out) = self.out.downcast_mut::<PinOut<T>>() {
+            // Safety: the reference is only pinned again by cast_pin, and is not moved from.
+            out.0 = Some(NonNull::from(unsafe { Pin::get_unchecked_mut(value) }));
+            self.filled = true;
+        }
+    }
+
+    /// Stores a box in a slot created by a boxed cast, after converting it with `unsize`, which
+    /// is usually the unsizing coercion `|value| value`. The box is handed back unchanged if `T`
+    /// is not the requested type, or if the slot was created for another kind of cast.
+    #[cfg(feature = "alloc")]
+    pub fn provide_box<T: ?Sized + 'static, S: ?Sized>(
+        &mut self,
+        value: Box<S>,
+        unsize: impl FnOnce(Box<S>) -> Box<T>,
+    ) -> Result<Provided<'s>, Box<S>> {
+        match self.out.downcast_mut::<BoxOut<T>>() {
+            Some(out) => {
+                out.0 = Some(unsize(value));
+                Ok(self.fill())
+            }
+            None => Err(value),
+        }
+    }
+
+    /// Stores a pinned box in a slot created by a pinned boxed cast, after converting it with
+    /// `unsize`. The box is handed back unchanged if `T` is not the requested type, or if the
+    /// slot was created for another kind of cast.
+    #[cfg(feature = "alloc")]
+    pub fn provide_pin_box<T: ?Sized + 'static, S: ?Sized>(
+        &mut self,
+        value: Pin<Box<S>>,
+        unsize: impl FnOnce(Pin<Box<S>>) -> Pin<Box<T>>,
+    ) -> Result<Provided<'s>, Pin<Box<S>>> {
+        match self.out.downcast_mut::<PinBoxOut<T>>() {
+            Some(out) => {
+                out.0 = Some(unsize(value));
+                Ok(self.fill())
+            }
+            None => Err(value),
+        }
+    }
+
+    /// Stores an `Rc` in a slot created by an `Rc` cast, after converting it with `unsize`. The
+    /// `Rc` is handed back unchanged if `T` is not the requested type, or if the slot was created
+    /// for another kind of cast.
+    #[cfg(feature = "alloc")]
+    pub fn provide_rc<T: ?Sized + 'static, S: ?Sized>(
+        &mut self,
+        value: Rc<S>,
+        unsize: impl FnOnce(Rc<S>) -> Rc<T>,
+    ) -> Result<Provided<'s>, Rc<S>> {
+        match self.out.downcast_mut::<RcOut<T>>() {
+            Some(out) => {
+                out.0 = Some(unsize(value));
+                Ok(self.fill())
+            }
+            None => Err(value),
+        }
+    }
+
+    /// Stores an `Arc` in a slot created by an `Arc` cast, after converting it with `unsize`. The
+    /// `Arc` is handed back unchanged if `T` is not the requested type, or if the slot was created
+    /// for another kind of cast.
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    pub fn provide_arc<T: ?Sized + 'static, S: ?Sized>(
+        &mut self,
+        value: Arc<S>,
+        unsize: impl FnOnce(Arc<S>) -> Arc<T>,
+    ) -> Result<Provided<'s>, Arc<S>> {
+        match self.out.downcast_mut::<ArcOut<T>>() {
+            Some(out) => {
+                out.0 = Some(unsize(value));
+                Ok(self.fill())
+            }
+            None => Err(value),
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn fill(&mut self) -> Provided<'s> {
+        self.filled = true;
+        Provided::new()
+    }
+}
+
+/// Returns true if `src` answers a shared cast to the trait object type with the `TypeId`
+/// `target`.
+#[doc(hidden)]
+pub fn answers(src: &dyn DowncastTrait, target: TypeId) -> bool {
+    let mut out = ProbeOut;
+    let mut slot = TraitSlot {
+        target,
+        out: &mut out,
+        filled: false,
+    };
+    src.convert_to_trait(&mut slot);
+    slot.is_filled()
+}
+
+/// Returns true if `src` would answer a boxed cast to the trait object type with the `TypeId`
+/// `target`. This is used by delegating implementations to decide whether to give up the outer
+/// object in a boxed cast.
+#[cfg(feature = "alloc")]
+#[doc(hidden)]
+pub fn answers_owned(src: &dyn DowncastTrait, target: TypeId) -> bool {
+    let mut out = OwnedProbeOut;
+    let mut slot = TraitSlot {
+        target,
+        out: &mut out,
+        filled: false,
+    };
+    src.convert_to_trait(&mut slot);
+    slot.is_filled()
+}
+
+/// Casts a shared [DowncastTrait](../trait.DowncastTrait.html) object to `T`.
+pub fn cast_ref<T: ?Sized + 'static>(src: &dyn DowncastTrait) -> Option<&T> {
+    let mut out = RefOut::<T>(None);
+    src.convert_to_trait(&mut TraitSlot::new::<T>(&mut out));
+    // Safety: the slot only accepts references living as long as the reborrow of src it was
+    // passed with. The implementation is generic over that lifetime, so the reference is valid
+    // for the whole borrow of src.
+    out.0.map(|ptr| unsafe { &*ptr.as_ptr() })
+}
+
+/// Casts a mutable [DowncastTrait](../trait.DowncastTrait.html) object to `T`.
+pub fn cast_mut<T: ?Sized + 'static>(src: &mut dyn DowncastTrait) -> Option<&mut T> {
+    let mut out = MutOut::<T>(None);
+    src.convert_to_trait_mut(&mut TraitSlot::new::<T>(&mut out));
+    // Safety: as in cast_ref, the reference is valid for the whole borrow of src, which stays
+    // mutably borrowed for as long as the result and is not used again by this function.
+    out.0.map(|ptr| unsafe { &mut *ptr.as_ptr() })
+}
+
+/// Casts a mutable [DowncastTrait](../trait.DowncastTrait.html) object to `T`, handing the
+/// reference back if the cast is not supported.
+pub fn try_cast_mut<T: ?Sized + 'static>(
+    src: &mut dyn DowncastTrait,
+) -> Result<&mut T, &mut dyn DowncastTrait> {
+    let mut out = MutOut::<T>(None);
+    src.convert_to_trait_mut(&mut TraitSlot::new::<T>(&mut out));
+    match out.0 {
+        // Safety: as in cast_mut. The reborrow of src the reference was provided from is not used
+        // again, and src is only handed back if the cast failed.
+        Some(ptr) => Ok(unsafe { &mut *ptr.as_ptr() }),
+        None => Err(src),
+    }
+}
+
+/// Casts a pointer to a [DowncastTrait](../trait.DowncastTrait.html) object to a pointer to `T`.
+/// The cast is dispatched through a shared reference, but the result is derived from `src`. Only
+/// an implementation answering with the whole object through [TraitSlot::provide_ptr] gives a
+/// result, so projected traits, casts forwarded to a `delegate` field and the parts of a
+/// `Composite` return None.
+///
+/// # Safety
+/// `src` must be valid for a shared borrow for the duration of the call. The shared reference
+/// the cast is dispatched through is created even though the result is not derived from it, so no
+/// mutable reference to the object, or to a value containing it such as a node of an intrusive
+/// list, may be live during the call.
+pub unsafe fn cast_ptr<T: ?Sized + 'static>(
+    src: NonNull<dyn DowncastTrait + '_>,
+) -> Option<NonNull<T>> {
+    let object = src.as_ref();
+    let mut out = PtrOut::<T> {
+        data: src.cast(),
+        size: size_of_val(object),
+        align: align_of_val(object),
+        answer: None,
+    };
+    object.convert_to_trait(&mut TraitSlot::new::<T>(&mut out));
+    out.answer
+}
+
+/// Casts a pinned mutable [DowncastTrait](../trait.DowncastTrait.html) object to `T`, keeping it
+/// pinned.
+pub fn cast_pin<T: ?Sized + 'static>(src: Pin<&mut dyn DowncastTrait>) -> Option<Pin<&mut T>> {
+    let mut out = PinOut::<T>(None);
+    src.convert_to_trait_pin(&mut TraitSlot::new::<T>(&mut out));
+    // Safety: as in cast_mut the reference is valid for the whole borrow of src. It was provided
+    // as a pinned reference, so it can be pinned again.
+    out.0
+        .map(|ptr| unsafe { Pin::new_unchecked(&mut *ptr.as_ptr()) })
+}
+
+/// Casts a boxed [DowncastTrait](../trait.DowncastTrait.html) object to `Box<T>`, handing the
+/// original box back if the cast is not supported.
+#[cfg(feature = "alloc")]
+pub fn cast_box<T: ?Sized + 'static>(
+    src: Box<dyn DowncastTrait>,
+) -> Result<Box<T>, Box<dyn DowncastTrait>> {
+    let mut out = BoxOut::<T>(None);
+    let _provided = src.convert_to_trait_box(&mut TraitSlot::new::<T>(&mut out))?;
+    Ok(out
+        .0
+        .unwrap_or_else(|| unreachable!("a slot is filled when it hands out a Provided")))
+}
+
+/// Casts a pinned boxed [DowncastTrait](../trait.DowncastTrait.html) object to `Pin<Box<T>>`,
+/// handing the original box back if the cast is not supported.
+#[cfg(feature = "alloc")]
+pub fn cast_pin_box<T: ?Sized + 'static>(
+    src: Pin<Box<dyn DowncastTrait>>,
+) -> Result<Pin<Box<T>>, Pin<Box<dyn DowncastTrait>>> {
+    let mut out = PinBoxOut::<T>(None);
+    let _provided = src.convert_to_trait_pin_box(&mut TraitSlot::new::<T>(&mut out))?;
+    Ok(out
+        .0
+        .unwrap_or_else(|| unreachable!("a slot is filled when it hands out a Provided")))
+}
+
+/// Casts a reference counted [DowncastTrait](../trait.DowncastTrait.html) object to `Rc<T>`,
+/// handing the original `Rc` back if the cast is not supported.
+#[cfg(feature = "alloc")]
+pub fn cast_rc<T: ?Sized + 'static>(
+    src: Rc<dyn DowncastTrait>,
+) -> Result<Rc<T>, Rc<dyn DowncastTrait>> {
+    let mut out = RcOut::<T>(None);
+    let _provided = src.convert_to_trait_rc(&mut TraitSlot::new::<T>(&mut out))?;
+    Ok(out
+        .0
+        .unwrap_or_else(|| unreachable!("a slot is filled when it hands out a Provided")))
+}
+
+/// Casts an atomically reference counted [DowncastTrait](../trait.DowncastTrait.html) object to
+/// `Arc<T>`, handing the original `Arc` back if the cast is not supported.
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+pub fn cast_arc<T: ?Sized + 'static>(
+    src: Arc<dyn DowncastTrait>,
+) -> Result<Arc<T>, Arc<dyn DowncastTrait>> {
+    let mut out = ArcOut::<T>(None);
+    let _provided = src.convert_to_trait_arc(&mut TraitSlot::new::<T>(&mut out))?;
+    Ok(out
+        .0
+        .unwrap_or_else(|| unreachable!("a slot is filled when it hands out a Provided")))
+}
@@ -0,0 +1,164 @@
+//! The conversions behind the provided methods of [DowncastTrait](../trait.DowncastTrait.html).
+//!
+//! A provided method of a trait can not unsize `self`, since the implementing type may itself be
+//! unsized. [AsDowncastTrait] is a supertrait of `DowncastTrait` that is implemented for every
+//! sized type, and does the conversions for the concrete type. It lives in a private module, so it
+//! can not be named or implemented outside this crate.
+#[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+use alloc::sync::Arc;
+#[cfg(feature = "alloc")]
+use alloc::{boxed::Box, rc::Rc};
+use core::{any::TypeId, pin::Pin};
+
+#[cfg(feature = "alloc")]
+use crate::Provided;
+use crate::{DowncastTrait, TraitDescriptor, TraitSlot};
+
+/// Conversions of the concrete type of a [DowncastTrait] object.
+pub trait AsDowncastTrait {
+    fn as_downcast_trait(&self) -> &dyn DowncastTrait;
+    fn as_downcast_trait_mut(&mut self) -> &mut dyn DowncastTrait;
+    #[cfg(feature = "alloc")]
+    fn into_downcast_trait_box(self: Box<Self>) -> Box<dyn DowncastTrait>;
+    #[cfg(feature = "alloc")]
+    fn into_downcast_trait_rc(self: Rc<Self>) -> Rc<dyn DowncastTrait>;
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn into_downcast_trait_arc(self: Arc<Self>) -> Arc<dyn DowncastTrait>;
+    #[cfg(feature = "alloc")]
+    fn unwrap_rc(self: Rc<Self>) -> Result<Box<dyn DowncastTrait>, Rc<dyn DowncastTrait>>;
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn unwrap_arc(self: Arc<Self>) -> Result<Box<dyn DowncastTrait>, Arc<dyn DowncastTrait>>;
+    fn concrete_type_id(&self) -> TypeId;
+    fn concrete_listed_traits(&self) -> &'static [TraitDescriptor];
+    /// Answers a cast to the concrete type, which is all the provided `convert_to_trait*` methods
+    /// do.
+    fn provide_self<'s>(&'s self, slot: &mut TraitSlot<'s>);
+    fn provide_self_mut<'s>(&'s mut self, slot: &mut TraitSlot<'s>);
+    fn provide_self_pin<'s>(self: Pin<&'s mut Self>, slot: &mut TraitSlot<'s>);
+    #[cfg(feature = "alloc")]
+    fn provide_self_box<'s>(
+        self: Box<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Box<dyn DowncastTrait>>;
+    #[cfg(feature = "alloc")]
+    fn provide_self_pin_box<'s>(
+        self: Pin<Box<Self>>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Pin<Box<dyn DowncastTrait>>>;
+    #[cfg(feature = "alloc")]
+    fn provide_self_rc<'s>(
+        self: Rc<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Rc<dyn DowncastTrait>>;
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn provide_self_arc<'s>(
+        self: Arc<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Arc<dyn DowncastTrait>>;
+}
+
+impl<T: DowncastTrait + 'static> AsDowncastTrait for T {
+    fn as_downcast_trait(&self) -> &dyn DowncastTrait {
+        self
+    }
+
+    fn as_downcast_trait_mut(&mut self) -> &mut dyn DowncastTrait {
+        self
+    }
+
+    #[cfg(feature = "alloc")]
+    fn into_downcast_trait_box(self: Box<Self>) -> Box<dyn DowncastTrait> {
+        self
+    }
+
+    #[cfg(feature = "alloc")]
+    fn into_downcast_trait_rc(self: Rc<Self>) -> Rc<dyn DowncastTrait> {
+        self
+    }
+
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn into_downcast_trait_arc(self: Arc<Self>) -> Arc<dyn DowncastTrait> {
+        self
+    }
+
+    #[cfg(feature = "alloc")]
+    fn unwrap_rc(self: Rc<Self>) -> Result<Box<dyn DowncastTrait>, Rc<dyn DowncastTrait>> {
+        match Rc::try_unwrap(self) {
+            Ok(value) => Ok(Box::new(value)),
+            Err(shared) => Err(shared),
+        }
+    }
+
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn unwrap_arc(self: Arc<Self>) -> Result<Box<dyn DowncastTrait>, Arc<dyn DowncastTrait>> {
+        match Arc::try_unwrap(self) {
+            Ok(value) => Ok(Box::new(value)),
+            Err(shared) => Err(shared),
+        }
+    }
+
+    fn concrete_type_id(&self) -> TypeId {
+        TypeId::of::<T>()
+    }
+
+    fn concrete_listed_traits(&self) -> &'static [TraitDescriptor] {
+        T::listed_traits()
+    }
+
+    fn provide_self<'s>(&'s self, slot: &mut TraitSlot<'s>) {
+        slot.provide_ref::<T>(self);
+        slot.provide_ptr::<T, T>(self, |this| this)
+    }
+
+    fn provide_self_mut<'s>(&'s mut self, slot: &mut TraitSlot<'s>) {
+        slot.provide_mut::<T>(self)
+    }
+
+    fn provide_self_pin<'s>(self: Pin<&'s mut Self>, slot: &mut TraitSlot<'s>) {
+        slot.provide_pin::<T>(self)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn provide_self_box<'s>(
+        self: Box<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Box<dyn DowncastTrait>> {
+        match slot.provide_box::<T, _>(self, |this| this) {
+            Ok(provided) => Ok(provided),
+            Err(this) => Err(this),
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn provide_self_pin_box<'s>(
+        self: Pin<Box<Self>>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Pin<Box<dyn DowncastTrait>>> {
+        match slot.provide_pin_box::<T, _>(self, |this| this) {
+            Ok(provided) => Ok(provided),
+            Err(this) => Err(this),
+        }
+    }
+
+    #[cfg(feature = "alloc")]
+    fn provide_self_rc<'s>(
+        self: Rc<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Rc<dyn DowncastTrait>> {
+        match slot.provide_rc::<T, _>(self, |this| this) {
+            Ok(provided) => Ok(provided),
+            Err(this) => Err(this),
+        }
+    }
+
+    #[cfg(all(feature = "alloc", target_has_atomic = "ptr"))]
+    fn provide_self_arc<'s>(
+        self: Arc<Self>,
+        slot: &mut TraitSlot<'s>,
+    ) -> Result<Provided<'s>, Arc<dyn DowncastTrait>> {
+        match slot.provide_arc::<T, _>(self, |this| this) {
+            Ok(provided) => Ok(provided),
+            Err(this) => Err(this),
+        }
+    }
+}